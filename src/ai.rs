@@ -1,34 +1,180 @@
-use crate::{level::LevelPits, OpaquePlugin, Orb, PlayerInput};
+use crate::{
+    collision,
+    level::LevelPits,
+    movement::{self, Handling, Shove},
+    nav::NavGrid,
+    personality::{self, DefaultPersonalities, Personalities},
+    AppState, OpaquePlugin, Orb, PlayerInput, TickSet,
+};
 use bevy::{ecs::system::EntityCommands, math::Vec3Swizzles, prelude::*};
 use bevy_rapier2d::prelude::*;
-use big_brain::prelude::*;
+use big_brain::{prelude::*, thinker::HasThinker};
 use std::time::Duration;
 
 const MIN_THRUST_PERIOD: Duration = Duration::from_millis(100);
 
+// tiles' worth of steps an orb will look for somewhere safer to flee to
+const REFUGE_REACH: u32 = 6;
+
+// how far ahead to anticipate where the player is going, when lining up a ram
+const RAM_LEAD: Duration = Duration::from_millis(500);
+
+// tiles; a player further than this from any pit isn't worth ramming
+const RAM_PIT_REACH: f32 = 4.0;
+
+// tiles back from the player, on the side away from the pit, to line up at
+const RAM_RUN_UP: f32 = 3.0;
+
+// cosines of how far off the line through the player to the pit a ram can start and carry on
+const RAM_ALIGNMENT: f32 = 0.95;
+const RAM_ALIGNMENT_LOST: f32 = 0.7;
+
+// tiles between centres at which a ram has connected
+const RAM_CONTACT: f32 = 1.25;
+
+// a player who can't be caught within this many seconds is as good as gone
+const INTERCEPT_HORIZON: f32 = 3.0;
+const INTERCEPT_STEPS: u32 = 60;
+
+// how long an orb keeps looking for a player it has lost sight of
+const SEARCH_PATIENCE: Duration = Duration::from_secs(5);
+
+/// What an orb knows of the player's whereabouts; it can't see through walls
+#[derive(Component, Default, Debug)]
+struct Perception {
+    sees_player: bool,
+    /// position and velocity when last seen
+    last_known: Option<(Vec2, Vec2)>,
+    since_seen: Duration,
+}
+
+impl Perception {
+    /// Where the player is and how fast they're going, if they're in sight
+    fn player(&self) -> Option<(Vec2, Vec2)> {
+        self.last_known.filter(|_| self.sees_player)
+    }
+}
+
+// with more than one player about, e.g. in versus, each orb keeps an eye on whoever is closest
+// of those still in play that it has a clear line to
+#[allow(clippy::type_complexity)]
+fn perceive(
+    time: Res<FixedTime>,
+    rapier: Res<RapierContext>,
+    players: Query<(&Transform, &Velocity), (With<PlayerInput>, With<Orb>)>,
+    mut orbs: Query<(&Transform, &mut Perception), Without<PlayerInput>>,
+) {
+    for (eye, mut perception) in orbs.iter_mut() {
+        let eye = eye.translation.xy();
+        let seen = players
+            .iter()
+            .map(|(transform, velocity)| (transform.translation.xy(), velocity.linvel))
+            .filter(|(player, _)| collision::line_of_sight(&rapier, eye, *player))
+            .min_by(|(a, _), (b, _)| a.distance_squared(eye).total_cmp(&b.distance_squared(eye)));
+
+        perception.sees_player = seen.is_some();
+        if seen.is_some() {
+            perception.last_known = seen;
+            perception.since_seen = Duration::ZERO;
+        } else {
+            perception.since_seen += time.period;
+        }
+    }
+}
+
+// the soonest an orb flat out could meet a target carrying on as it is, and where; ignores walls.
+// thrusting only adds to the drift up to top speed, so it can never have got further than that
+fn intercept(
+    handling: &Handling,
+    from: Vec2,
+    velocity: Vec2,
+    target: Vec2,
+    target_velocity: Vec2,
+) -> Option<(Vec2, f32)> {
+    (1..=INTERCEPT_STEPS)
+        .map(|step| step as f32 * INTERCEPT_HORIZON / INTERCEPT_STEPS as f32)
+        .find(|&seconds| {
+            let meeting = target + target_velocity * seconds;
+            let drift = from + velocity * seconds;
+            meeting.distance(drift) <= handling.reach(seconds)
+                && meeting.distance(from) <= handling.max_speed * seconds
+        })
+        .map(|seconds| (target + target_velocity * seconds, seconds))
+}
+
+/// The way to shove a player into the pit nearest to where they're headed
+struct RamLine {
+    target: Vec2,
+    to_pit: Vec2,
+    /// in tiles
+    pit_distance: f32,
+}
+
+impl RamLine {
+    fn new(pits: &LevelPits, player_loc: Vec2, player_velocity: Vec2) -> Option<Self> {
+        let target = player_loc + player_velocity * RAM_LEAD.as_secs_f32();
+        let vector_to_pit = pits.nearest_pit(&target);
+        let pit_distance = vector_to_pit.length() / 256.0;
+
+        // with no pits at all, the nearest is infinitely far away
+        (pit_distance <= RAM_PIT_REACH).then(|| RamLine {
+            target,
+            to_pit: vector_to_pit.normalize_or_zero(),
+            pit_distance,
+        })
+    }
+
+    fn run_up(&self) -> Vec2 {
+        self.target - self.to_pit * RAM_RUN_UP * 256.0
+    }
+
+    /// 1 if the player is directly between here and the pit
+    fn alignment(&self, from: Vec2) -> f32 {
+        (self.target - from).normalize_or_zero().dot(self.to_pit)
+    }
+}
+
 #[derive(Clone, Component, Debug, ActionBuilder)]
 struct Halt;
 
+#[allow(clippy::type_complexity)]
 fn halt_action(
-    time: Res<Time>,
+    time: Res<FixedTime>,
     mut orbs: Query<
-        (&mut Transform, &mut Velocity, &mut ExternalImpulse),
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut ExternalImpulse,
+            &Handling,
+        ),
         (With<Orb>, Without<PlayerInput>),
     >,
     mut actions: Query<(&Actor, &mut ActionState), With<Halt>>,
 ) {
+    let dt = time.period.as_secs_f32();
+
     for (Actor(actor), mut state) in actions.iter_mut() {
-        if let Ok((_, mut velocity, mut impulse)) = orbs.get_mut(*actor) {
+        if let Ok((_, mut velocity, mut impulse, handling)) = orbs.get_mut(*actor) {
             match *state {
                 ActionState::Requested => {
-                    crate::movement::decelerate_orb(&time, velocity.as_mut(), impulse.as_mut());
+                    crate::movement::decelerate_orb(
+                        dt,
+                        handling,
+                        velocity.as_mut(),
+                        impulse.as_mut(),
+                    );
                     *state = ActionState::Executing;
                 }
                 ActionState::Executing => {
                     if velocity.angvel == 0.0 && velocity.linvel == Vec2::ZERO {
                         *state = ActionState::Success;
                     } else {
-                        crate::movement::decelerate_orb(&time, velocity.as_mut(), impulse.as_mut());
+                        crate::movement::decelerate_orb(
+                            dt,
+                            handling,
+                            velocity.as_mut(),
+                            impulse.as_mut(),
+                        );
                     }
                 }
                 ActionState::Cancelled => {
@@ -43,14 +189,17 @@ fn halt_action(
 #[derive(Clone, Component, Debug, ActionBuilder)]
 struct RelativeMove {
     r#type: MoveType,
+    /// how near or far to get before stopping
+    tiles: f32,
     thrust: Option<Vec2>,
     since: Duration,
 }
 
-impl From<MoveType> for RelativeMove {
-    fn from(value: MoveType) -> Self {
+impl RelativeMove {
+    fn new(r#type: MoveType, tiles: f32) -> Self {
         RelativeMove {
-            r#type: value,
+            r#type,
+            tiles,
             thrust: None,
             since: Duration::ZERO,
         }
@@ -62,62 +211,122 @@ enum MoveType {
     AvoidPit,
     AvoidPlayer,
     ChasePlayer,
+    SearchPlayer,
 }
 
+// moving relative to the player goes the long way round walls and pits, one waypoint per thrust;
+// chasing heads for where they're going to be, if that's anywhere they can be caught
+#[allow(clippy::type_complexity)]
 fn relative_move_action(
-    time: Res<Time>,
+    time: Res<FixedTime>,
     pits: Res<LevelPits>,
-    player: Query<&Transform, With<PlayerInput>>,
+    nav: Res<NavGrid>,
     mut orbs: Query<
-        (&mut Transform, &mut Velocity, &mut ExternalImpulse),
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut ExternalImpulse,
+            &Handling,
+            &mut Perception,
+        ),
         (With<Orb>, Without<PlayerInput>),
     >,
     mut actions: Query<(&Actor, &mut ActionState, &mut RelativeMove)>,
 ) {
+    let dt = time.period.as_secs_f32();
+
     for (Actor(actor), mut state, mut action) in actions.iter_mut() {
-        if let Ok((mut transform, mut velocity, mut impulse)) = orbs.get_mut(*actor) {
+        if let Ok((mut transform, mut velocity, mut impulse, handling, mut perception)) =
+            orbs.get_mut(*actor)
+        {
             let (precondition_failed, reached_goal, mut thrust) = match action.r#type {
                 MoveType::AvoidPit => {
                     let vector_to_pit = pits.nearest_pit(&transform.translation.xy());
                     let distance_to_pit = vector_to_pit.length() / 256.0;
-                    (false, distance_to_pit >= 3.0, -vector_to_pit.normalize())
+                    (
+                        false,
+                        distance_to_pit >= action.tiles,
+                        -vector_to_pit.normalize(),
+                    )
                 }
                 MoveType::AvoidPlayer => {
-                    if let Ok(Transform {
-                        translation: player_loc,
-                        ..
-                    }) = player.get_single()
-                    {
-                        let vector_to_player = *player_loc - transform.translation;
-                        let distance_to_player = vector_to_player.length() / 256.0;
-                        (
-                            false,
-                            distance_to_player >= 3.0,
-                            -vector_to_player.normalize().xy(),
-                        )
+                    if let Some((player_loc, _)) = perception.player() {
+                        let orb_loc = transform.translation.xy();
+                        let distance_to_player = orb_loc.distance(player_loc) / 256.0;
+                        let waypoint = nav
+                            .refuge(orb_loc, player_loc, REFUGE_REACH)
+                            .and_then(|refuge| nav.path(orb_loc, refuge))
+                            .and_then(|path| path.first().copied());
+
+                        match waypoint {
+                            Some(waypoint) => (
+                                false,
+                                distance_to_player >= action.tiles,
+                                (waypoint - orb_loc).normalize_or_zero(),
+                            ),
+                            // cornered
+                            None => (true, false, Vec2::ZERO),
+                        }
                     } else {
                         (true, false, Vec2::ZERO)
                     }
                 }
                 MoveType::ChasePlayer => {
-                    if let Ok(Transform {
-                        translation: player_loc,
-                        ..
-                    }) = player.get_single()
-                    {
-                        let vector_to_player = *player_loc - transform.translation;
-                        let distance_to_player = vector_to_player.length() / 256.0;
-                        (
-                            false,
-                            distance_to_player <= 3.0,
-                            vector_to_player.normalize().xy(),
+                    if let Some((player_loc, player_velocity)) = perception.player() {
+                        let orb_loc = transform.translation.xy();
+                        let distance_to_player = orb_loc.distance(player_loc) / 256.0;
+                        let meeting = intercept(
+                            handling,
+                            orb_loc,
+                            velocity.linvel,
+                            player_loc,
+                            player_velocity,
                         )
+                        .map_or(player_loc, |(meeting, _)| meeting);
+                        let waypoint = nav
+                            .path(orb_loc, meeting)
+                            .and_then(|path| path.first().copied());
+
+                        match waypoint {
+                            Some(waypoint) => (
+                                false,
+                                distance_to_player <= action.tiles,
+                                (waypoint - orb_loc).normalize_or_zero(),
+                            ),
+                            // out of reach, e.g. across a pit
+                            None => (true, false, Vec2::ZERO),
+                        }
+                    } else {
+                        (true, false, Vec2::ZERO)
+                    }
+                }
+                MoveType::SearchPlayer => {
+                    if let Some((last_known, _)) = perception.last_known {
+                        let orb_loc = transform.translation.xy();
+                        let distance_to_last_known = orb_loc.distance(last_known) / 256.0;
+                        let waypoint = nav
+                            .path(orb_loc, last_known)
+                            .and_then(|path| path.first().copied());
+
+                        match waypoint {
+                            Some(waypoint) => (
+                                false,
+                                distance_to_last_known <= action.tiles,
+                                (waypoint - orb_loc).normalize_or_zero(),
+                            ),
+                            None => (true, false, Vec2::ZERO),
+                        }
                     } else {
                         (true, false, Vec2::ZERO)
                     }
                 }
             };
 
+            // the trail goes cold once there's nobody where the player was last seen
+            if reached_goal && matches!(action.r#type, MoveType::SearchPlayer) {
+                perception.last_known = None;
+            }
+
             debug!("RelativeMove spec: failed({precondition_failed}) completed({reached_goal}) thrust({thrust})");
 
             if precondition_failed {
@@ -136,7 +345,8 @@ fn relative_move_action(
                             ActionState::Success
                         } else {
                             if !crate::movement::accelerate_orb(
-                                &time,
+                                dt,
+                                handling,
                                 thrust,
                                 transform.as_mut(),
                                 velocity.as_mut(),
@@ -144,7 +354,7 @@ fn relative_move_action(
                             ) {
                                 ActionState::Executing
                             } else {
-                                action.since += time.delta();
+                                action.since += time.period;
                                 if action.since >= MIN_THRUST_PERIOD {
                                     ActionState::Success
                                 } else {
@@ -163,67 +373,320 @@ fn relative_move_action(
     }
 }
 
-/// intent to stay away from the player
+/// wind up a shove while turning to face the player, then let it go
+#[derive(Clone, Component, Debug, ActionBuilder)]
+struct ShovePlayer;
+
+#[allow(clippy::type_complexity)]
+fn shove_player_action(
+    time: Res<FixedTime>,
+    mut orbs: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut ExternalImpulse,
+            &Handling,
+            &mut Shove,
+            &Perception,
+        ),
+        (With<Orb>, Without<PlayerInput>),
+    >,
+    mut actions: Query<(&Actor, &mut ActionState), With<ShovePlayer>>,
+) {
+    let dt = time.period.as_secs_f32();
+
+    for (Actor(actor), mut state) in actions.iter_mut() {
+        if let Ok((mut transform, mut velocity, mut impulse, handling, mut shove, perception)) =
+            orbs.get_mut(*actor)
+        {
+            match *state {
+                ActionState::Requested | ActionState::Executing => {
+                    let Some((player_loc, _)) = perception.player() else {
+                        *state = ActionState::Failure;
+                        continue;
+                    };
+
+                    if !movement::charge_shove(time.period, shove.as_mut()) {
+                        *state = ActionState::Failure;
+                        continue;
+                    }
+
+                    let vector_to_player = player_loc - transform.translation.xy();
+                    let facing = movement::turn_orb(
+                        dt,
+                        handling,
+                        vector_to_player,
+                        transform.as_mut(),
+                        velocity.as_mut(),
+                    );
+
+                    *state = if facing && shove.charge_level() >= 1.0 {
+                        movement::release_shove(&transform, shove.as_mut(), impulse.as_mut());
+                        ActionState::Success
+                    } else {
+                        ActionState::Executing
+                    };
+                }
+                ActionState::Cancelled => {
+                    // an interrupted windup is wasted
+                    shove.charge = Duration::ZERO;
+                    *state = ActionState::Failure;
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// get round behind the player, opposite a pit, then drive into them at full speed
+#[derive(Clone, Component, Debug, Default, ActionBuilder)]
+struct RamPlayer {
+    lined_up: bool,
+}
+
+// the approach finds its way round walls and pits, but the ram itself is a straight line
+#[allow(clippy::type_complexity)]
+fn ram_player_action(
+    time: Res<FixedTime>,
+    pits: Res<LevelPits>,
+    nav: Res<NavGrid>,
+    mut orbs: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &mut ExternalImpulse,
+            &Handling,
+            &Perception,
+        ),
+        (With<Orb>, Without<PlayerInput>),
+    >,
+    mut actions: Query<(&Actor, &mut ActionState, &mut RamPlayer)>,
+) {
+    let dt = time.period.as_secs_f32();
+
+    for (Actor(actor), mut state, mut ram) in actions.iter_mut() {
+        if let Ok((mut transform, mut velocity, mut impulse, handling, perception)) =
+            orbs.get_mut(*actor)
+        {
+            match *state {
+                ActionState::Requested | ActionState::Executing => {
+                    let orb_loc = transform.translation.xy();
+                    let Some((player_loc, player_velocity)) = perception.player() else {
+                        *state = ActionState::Failure;
+                        continue;
+                    };
+                    let Some(line) = RamLine::new(&pits, player_loc, player_velocity) else {
+                        *state = ActionState::Failure;
+                        continue;
+                    };
+
+                    let alignment = line.alignment(orb_loc);
+                    ram.lined_up = if ram.lined_up {
+                        alignment >= RAM_ALIGNMENT_LOST
+                    } else {
+                        alignment >= RAM_ALIGNMENT
+                    };
+
+                    let thrust = if ram.lined_up {
+                        if orb_loc.distance(player_loc) / 256.0 <= RAM_CONTACT {
+                            *state = ActionState::Success;
+                            continue;
+                        }
+                        (line.target - orb_loc).normalize_or_zero()
+                    } else {
+                        let Some(waypoint) = nav
+                            .path(orb_loc, line.run_up())
+                            .and_then(|path| path.first().copied())
+                        else {
+                            *state = ActionState::Failure;
+                            continue;
+                        };
+                        (waypoint - orb_loc).normalize_or_zero()
+                    };
+
+                    debug!(
+                        "RamPlayer spec: lined_up({}) thrust({thrust})",
+                        ram.lined_up
+                    );
+
+                    crate::movement::accelerate_orb(
+                        dt,
+                        handling,
+                        thrust,
+                        transform.as_mut(),
+                        velocity.as_mut(),
+                        impulse.as_mut(),
+                    );
+                    *state = ActionState::Executing;
+                }
+                ActionState::Cancelled => {
+                    *state = ActionState::Failure;
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// intent to stay away from the player, while they're in sight
 #[derive(Clone, Component, Debug, ScorerBuilder)]
-struct Flee;
+struct Flee {
+    tiles: f32,
+}
 
 fn flee_scorer(
-    player: Query<&Transform, With<PlayerInput>>,
-    enemies: Query<&Transform, Without<PlayerInput>>,
-    mut scorers: Query<(&Actor, &mut Score), With<Flee>>,
+    enemies: Query<(&Transform, &Perception)>,
+    mut scorers: Query<(&Actor, &mut Score, &Flee)>,
 ) {
-    if let Ok(Transform {
-        translation: player_loc,
-        ..
-    }) = player.get_single()
-    {
-        for (Actor(actor), mut score) in &mut scorers {
-            if let Ok(Transform {
-                translation: enemy_loc,
-                ..
-            }) = enemies.get(*actor)
-            {
-                let distance_to_player = enemy_loc.distance(*player_loc) / 256.0;
-                let distance_within_3 = (3.0 - distance_to_player).clamp(0.0, 3.0);
+    for (Actor(actor), mut score, flee) in &mut scorers {
+        if let Ok((transform, perception)) = enemies.get(*actor) {
+            let Some((player_loc, _)) = perception.player() else {
+                score.set(0.0);
+                continue;
+            };
 
-                if !distance_within_3.is_nan() {
-                    score.set(distance_within_3 / 3.0);
-                } else {
-                    score.set(0.0);
-                }
+            let distance_to_player = transform.translation.xy().distance(player_loc) / 256.0;
+            let distance_within = (flee.tiles - distance_to_player).clamp(0.0, flee.tiles);
+
+            if !distance_within.is_nan() {
+                score.set(distance_within / flee.tiles);
+            } else {
+                score.set(0.0);
             }
         }
     }
 }
 
-/// intent to get near the player
+/// intent to get near the player, while they're in sight
 #[derive(Clone, Component, Debug, ScorerBuilder)]
-struct Charge;
+struct Charge {
+    tiles: f32,
+}
 
 fn charge_scorer(
-    player: Query<&Transform, With<PlayerInput>>,
-    enemies: Query<&Transform, Without<PlayerInput>>,
-    mut scorers: Query<(&Actor, &mut Score), With<Charge>>,
+    enemies: Query<(&Transform, &Perception)>,
+    mut scorers: Query<(&Actor, &mut Score, &Charge)>,
 ) {
-    if let Ok(Transform {
-        translation: player_loc,
-        ..
-    }) = player.get_single()
-    {
-        for (Actor(actor), mut score) in &mut scorers {
-            if let Ok(Transform {
-                translation: enemy_loc,
-                ..
-            }) = enemies.get(*actor)
-            {
-                let distance_to_player = enemy_loc.distance(*player_loc) / 256.0;
-                let distance_beyond_3 = (distance_to_player - 3.0).clamp(0.0, 3.0);
+    for (Actor(actor), mut score, charge) in &mut scorers {
+        if let Ok((transform, perception)) = enemies.get(*actor) {
+            let Some((player_loc, _)) = perception.player() else {
+                score.set(0.0);
+                continue;
+            };
 
-                if !distance_beyond_3.is_nan() {
-                    score.set(distance_beyond_3 / 3.0);
-                } else {
-                    score.set(0.0);
-                }
+            let distance_to_player = transform.translation.xy().distance(player_loc) / 256.0;
+            let distance_beyond = (distance_to_player - charge.tiles).clamp(0.0, charge.tiles);
+
+            if !distance_beyond.is_nan() {
+                score.set(distance_beyond / charge.tiles);
+            } else {
+                score.set(0.0);
+            }
+        }
+    }
+}
+
+/// how soon the player could be caught, if at all
+#[derive(Clone, Component, Debug, ScorerBuilder)]
+struct Catchable;
+
+fn catchable_scorer(
+    enemies: Query<(&Transform, &Velocity, &Handling, &Perception)>,
+    mut scorers: Query<(&Actor, &mut Score), With<Catchable>>,
+) {
+    for (Actor(actor), mut score) in &mut scorers {
+        if let Ok((transform, velocity, handling, perception)) = enemies.get(*actor) {
+            let Some((player_loc, player_velocity)) = perception.player() else {
+                score.set(0.0);
+                continue;
+            };
+
+            match intercept(
+                handling,
+                transform.translation.xy(),
+                velocity.linvel,
+                player_loc,
+                player_velocity,
+            ) {
+                Some((_, seconds)) => score.set(1.0 - seconds / INTERCEPT_HORIZON),
+                None => score.set(0.0),
+            }
+        }
+    }
+}
+
+/// opportunity to shove the player from close range
+#[derive(Clone, Component, Debug, ScorerBuilder)]
+struct InShoveRange {
+    tiles: f32,
+}
+
+fn shove_range_scorer(
+    enemies: Query<(&Transform, &Shove, &Perception)>,
+    mut scorers: Query<(&Actor, &mut Score, &InShoveRange)>,
+) {
+    for (Actor(actor), mut score, range) in &mut scorers {
+        if let Ok((transform, shove, perception)) = enemies.get(*actor) {
+            let Some((player_loc, _)) = perception.player() else {
+                score.set(0.0);
+                continue;
+            };
+
+            let distance_to_player = transform.translation.xy().distance(player_loc) / 256.0;
+
+            if shove.ready() && distance_to_player <= range.tiles {
+                score.set(0.5);
+            } else {
+                score.set(0.0);
+            }
+        }
+    }
+}
+
+/// opportunity to knock the player into a pit, the closer they are to it the better
+#[derive(Clone, Component, Debug, ScorerBuilder)]
+struct PlayerNearPit;
+
+fn player_near_pit_scorer(
+    pits: Res<LevelPits>,
+    enemies: Query<&Perception>,
+    mut scorers: Query<(&Actor, &mut Score), With<PlayerNearPit>>,
+) {
+    for (Actor(actor), mut score) in &mut scorers {
+        if let Ok(perception) = enemies.get(*actor) {
+            let line = perception
+                .player()
+                .and_then(|(player_loc, player_velocity)| {
+                    RamLine::new(&pits, player_loc, player_velocity)
+                });
+
+            match line {
+                Some(line) => score.set(0.75 * (1.0 - line.pit_distance / RAM_PIT_REACH)),
+                None => score.set(0.0),
+            }
+        }
+    }
+}
+
+/// fading urge to look for the player where they were last seen
+#[derive(Clone, Component, Debug, ScorerBuilder)]
+struct LostSight;
+
+fn lost_sight_scorer(
+    enemies: Query<&Perception>,
+    mut scorers: Query<(&Actor, &mut Score), With<LostSight>>,
+) {
+    for (Actor(actor), mut score) in &mut scorers {
+        if let Ok(perception) = enemies.get(*actor) {
+            if !perception.sees_player
+                && perception.last_known.is_some()
+                && perception.since_seen < SEARCH_PATIENCE
+            {
+                let patience_left =
+                    1.0 - perception.since_seen.as_secs_f32() / SEARCH_PATIENCE.as_secs_f32();
+                score.set(0.4 * patience_left);
+            } else {
+                score.set(0.0);
             }
         }
     }
@@ -250,21 +713,23 @@ fn moving_scorer(
 
 /// high-value fear of pits
 #[derive(Clone, Component, Debug, ScorerBuilder)]
-struct NearPit;
+struct NearPit {
+    tiles: f32,
+}
 
 fn near_pit_scorer(
     pits: Res<LevelPits>,
     orbs: Query<&Transform, With<Orb>>,
-    mut scorers: Query<(&Actor, &mut Score), With<NearPit>>,
+    mut scorers: Query<(&Actor, &mut Score, &NearPit)>,
 ) {
-    for (Actor(actor), mut score) in &mut scorers {
+    for (Actor(actor), mut score, near_pit) in &mut scorers {
         if let Ok(transform) = orbs.get(*actor) {
             let pit_vec = pits.nearest_pit(&transform.translation.xy());
             let pit_dist = pit_vec.length();
 
             debug!("pit_vec({pit_vec}) pit_dist({pit_dist})");
 
-            if pit_dist < 256.0 * 3.0 {
+            if pit_dist < 256.0 * near_pit.tiles {
                 score.set(1.0);
             } else {
                 score.set(0.0);
@@ -275,54 +740,260 @@ fn near_pit_scorer(
 
 pub fn plugin() -> impl Plugin {
     OpaquePlugin(|app| {
-        app.add_plugins(BigBrainPlugin::new(PreUpdate))
+        app.add_plugins(BigBrainPlugin::new(FixedUpdate))
+            .configure_set(
+                FixedUpdate,
+                BigBrainSet::Cleanup.before(PhysicsSet::SyncBackend),
+            )
+            // thinkers hold still along with the rest of the world, e.g. while paused
+            .configure_set(
+                FixedUpdate,
+                BigBrainSet::Scorers.run_if(in_state(AppState::Playing)),
+            )
+            .configure_set(
+                FixedUpdate,
+                BigBrainSet::Thinkers.run_if(in_state(AppState::Playing)),
+            )
+            .configure_set(
+                FixedUpdate,
+                BigBrainSet::Actions
+                    .in_set(TickSet::Movement)
+                    .before(super::trigger_vfx)
+                    .run_if(in_state(AppState::Playing)),
+            )
             .add_systems(
-                PreUpdate,
-                (relative_move_action, halt_action).in_set(BigBrainSet::Actions),
+                FixedUpdate,
+                (
+                    relative_move_action,
+                    halt_action,
+                    shove_player_action,
+                    ram_player_action,
+                )
+                    .in_set(BigBrainSet::Actions),
             )
+            .add_systems(Update, reload_personalities)
             .add_systems(
-                PreUpdate,
-                (moving_scorer, near_pit_scorer, flee_scorer, charge_scorer)
+                FixedUpdate,
+                perceive
+                    .before(BigBrainSet::Scorers)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    moving_scorer,
+                    near_pit_scorer,
+                    flee_scorer,
+                    charge_scorer,
+                    catchable_scorer,
+                    shove_range_scorer,
+                    player_near_pit_scorer,
+                    lost_sight_scorer,
+                )
                     .in_set(BigBrainSet::Scorers),
             );
     })
 }
 
-pub fn spawn_intransigence(entity: &mut EntityCommands) {
-    entity.insert(
-        Thinker::build()
-            .label("intransigence")
-            .picker(Highest)
-            .when(ExperiencingInertia, Halt),
-    );
-}
-
-pub fn spawn_cowardice(entity: &mut EntityCommands) {
-    entity.insert(
-        Thinker::build()
-            .label("cowardice")
-            .picker(FirstToScore { threshold: 0.5 })
-            .when(NearPit, RelativeMove::from(MoveType::AvoidPit))
-            .otherwise(
-                Thinker::build()
-                    .picker(Highest)
-                    .when(Flee, RelativeMove::from(MoveType::AvoidPlayer))
-                    .when(ExperiencingInertia, Halt),
-            ),
-    );
-}
-
-pub fn spawn_malice(entity: &mut EntityCommands) {
-    entity.insert(
-        Thinker::build()
-            .label("malice")
-            .picker(FirstToScore { threshold: 0.5 })
-            .when(NearPit, RelativeMove::from(MoveType::AvoidPit))
-            .otherwise(
-                Thinker::build()
-                    .picker(Highest)
-                    .when(Charge, RelativeMove::from(MoveType::ChasePlayer))
-                    .when(ExperiencingInertia, Halt),
-            ),
-    );
+/// The name an orb's thinker was built from, so that it can be rebuilt if that changes
+#[derive(Component)]
+struct Personality(String);
+
+impl ScorerBuilder for personality::Scorer {
+    fn build(&self, cmd: &mut Commands, scorer: Entity, actor: Entity) {
+        match self {
+            personality::Scorer::ExperiencingInertia => {
+                ExperiencingInertia.build(cmd, scorer, actor)
+            }
+            personality::Scorer::NearPit { tiles } => {
+                NearPit { tiles: *tiles }.build(cmd, scorer, actor)
+            }
+            personality::Scorer::Flee { tiles } => Flee { tiles: *tiles }.build(cmd, scorer, actor),
+            personality::Scorer::Charge { tiles } => {
+                Charge { tiles: *tiles }.build(cmd, scorer, actor)
+            }
+            personality::Scorer::Catchable => Catchable.build(cmd, scorer, actor),
+            personality::Scorer::InShoveRange { tiles } => {
+                InShoveRange { tiles: *tiles }.build(cmd, scorer, actor)
+            }
+            personality::Scorer::PlayerNearPit => PlayerNearPit.build(cmd, scorer, actor),
+            personality::Scorer::LostSight => LostSight.build(cmd, scorer, actor),
+            personality::Scorer::Product { threshold, scorers } => scorers
+                .iter()
+                .fold(ProductOfScorers::build(*threshold), |product, factor| {
+                    product.push(factor.clone())
+                })
+                .build(cmd, scorer, actor),
+        }
+    }
+}
+
+impl ActionBuilder for personality::Action {
+    fn build(&self, cmd: &mut Commands, action: Entity, actor: Entity) {
+        match self {
+            personality::Action::Halt => Halt.build(cmd, action, actor),
+            personality::Action::AvoidPit { tiles } => {
+                RelativeMove::new(MoveType::AvoidPit, *tiles).build(cmd, action, actor)
+            }
+            personality::Action::AvoidPlayer { tiles } => {
+                RelativeMove::new(MoveType::AvoidPlayer, *tiles).build(cmd, action, actor)
+            }
+            personality::Action::ChasePlayer { tiles } => {
+                RelativeMove::new(MoveType::ChasePlayer, *tiles).build(cmd, action, actor)
+            }
+            personality::Action::SearchPlayer { tiles } => {
+                RelativeMove::new(MoveType::SearchPlayer, *tiles).build(cmd, action, actor)
+            }
+            personality::Action::ShovePlayer => ShovePlayer.build(cmd, action, actor),
+            personality::Action::RamPlayer => RamPlayer::default().build(cmd, action, actor),
+            personality::Action::Think(thinking) => thinker(thinking).build(cmd, action, actor),
+        }
+    }
+}
+
+fn thinker(thinking: &personality::Thinking) -> ThinkerBuilder {
+    let mut thinker = match thinking.picker {
+        personality::Picker::Highest => Thinker::build().picker(Highest),
+        personality::Picker::FirstToScore { threshold } => {
+            Thinker::build().picker(FirstToScore { threshold })
+        }
+    };
+
+    for choice in &thinking.choices {
+        thinker = thinker.when(choice.when.clone(), choice.then.clone());
+    }
+
+    if let Some(otherwise) = &thinking.otherwise {
+        thinker = thinker.otherwise(otherwise.clone());
+    }
+
+    thinker
+}
+
+/// Attaches the named personality's thinker, returning false if there is no such personality
+pub fn spawn_personality(
+    entity: &mut EntityCommands,
+    personalities: &Personalities,
+    name: &str,
+) -> bool {
+    let Some(thinking) = personalities.get(name) else {
+        return false;
+    };
+
+    entity.insert((
+        thinker(thinking).label(name),
+        Personality(name.to_owned()),
+        Perception::default(),
+    ));
+    true
+}
+
+// big-brain only gives a thinker to an orb without one, so the old one has to go first
+fn reload_personalities(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Personalities>>,
+    defaults: Res<DefaultPersonalities>,
+    personalities: Res<Assets<Personalities>>,
+    orbs: Query<(Entity, &Personality, Option<&HasThinker>)>,
+) {
+    let modified = events
+        .iter()
+        .any(|event| matches!(event, AssetEvent::Modified { handle } if *handle == defaults.0));
+    let Some(personalities) = personalities.get(&defaults.0).filter(|_| modified) else {
+        return;
+    };
+
+    for (entity, Personality(name), has_thinker) in orbs.iter() {
+        if let Some(has_thinker) = has_thinker {
+            commands.entity(has_thinker.entity()).despawn_recursive();
+        }
+
+        let mut orb = commands.entity(entity);
+        orb.remove::<(ThinkerBuilder, HasThinker)>();
+        match personalities.get(name) {
+            Some(thinking) => {
+                orb.insert(thinker(thinking).label(name));
+            }
+            None => warn!("personality '{name}' has gone away"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reaches top speed after two seconds, having covered 200 pixels
+    const HANDLING: Handling = Handling {
+        accel: 100.0,
+        decel: 100.0,
+        turn_rate: 1.0,
+        max_speed: 200.0,
+    };
+
+    #[test]
+    fn intercept_still_target() {
+        let target = Vec2::new(100.0, 0.0);
+
+        let (meeting, seconds) =
+            intercept(&HANDLING, Vec2::ZERO, Vec2::ZERO, target, Vec2::ZERO).unwrap();
+
+        assert_eq!(meeting, target);
+        assert!((seconds - 2f32.sqrt()).abs() <= INTERCEPT_HORIZON / INTERCEPT_STEPS as f32);
+    }
+
+    #[test]
+    fn intercept_faster_target_getting_away() {
+        let target = Vec2::new(500.0, 0.0);
+        let target_velocity = Vec2::new(2.0 * HANDLING.max_speed, 0.0);
+
+        assert_eq!(
+            intercept(&HANDLING, Vec2::ZERO, Vec2::ZERO, target, target_velocity),
+            None
+        );
+    }
+
+    #[test]
+    fn intercept_faster_target_coming_closer() {
+        let target = Vec2::new(500.0, 0.0);
+        let target_velocity = Vec2::new(-2.0 * HANDLING.max_speed, 0.0);
+
+        let (meeting, seconds) =
+            intercept(&HANDLING, Vec2::ZERO, Vec2::ZERO, target, target_velocity).unwrap();
+
+        // met on the way, before the target would have gone straight past
+        assert!(seconds < 500.0 / (2.0 * HANDLING.max_speed));
+        assert!(meeting.x > 0.0 && meeting.x < target.x);
+    }
+
+    #[test]
+    fn intercept_counts_drift() {
+        let target = Vec2::new(300.0, 0.0);
+        let target_velocity = Vec2::new(0.25 * HANDLING.max_speed, 0.0);
+        let velocity = Vec2::new(HANDLING.max_speed, 0.0);
+
+        // from rest, it couldn't get up to speed in time
+        assert_eq!(
+            intercept(&HANDLING, Vec2::ZERO, Vec2::ZERO, target, target_velocity),
+            None
+        );
+
+        // already flat out, it only has to make up the distance
+        let (_, seconds) =
+            intercept(&HANDLING, Vec2::ZERO, velocity, target, target_velocity).unwrap();
+        assert!((seconds - 2.0).abs() <= INTERCEPT_HORIZON / INTERCEPT_STEPS as f32);
+    }
+
+    #[test]
+    fn intercept_never_beyond_top_speed() {
+        let target = Vec2::new(300.0, 0.0);
+        let target_velocity = Vec2::new(0.75 * HANDLING.max_speed, 0.0);
+        let velocity = Vec2::new(HANDLING.max_speed, 0.0);
+
+        // thrusting on top of the drift would seem to close the gap, but it's already flat out
+        assert_eq!(
+            intercept(&HANDLING, Vec2::ZERO, velocity, target, target_velocity),
+            None
+        );
+    }
 }
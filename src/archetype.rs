@@ -0,0 +1,221 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::BoxedFuture,
+};
+use bevy_ecs_ldtk::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::{controls::Seat, movement, OpaquePlugin};
+
+// the orb sprites are drawn at this radius, on a tile-sized canvas
+pub const ORB_RADIUS: f32 = 100.0;
+pub const ORB_SPRITE_SIZE: f32 = 256.0;
+
+/// Whose side an orb is on, which decides how the level is won or lost
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Role {
+    Player,
+    Enemy,
+    #[default]
+    Neutral,
+}
+
+/// Everything that distinguishes one kind of orb from another
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Archetype {
+    pub role: Role,
+    /// which player steers it, for player orbs; the second only takes part in versus
+    pub seat: Seat,
+    pub mass: f32,
+    pub radius: f32,
+    pub restitution: f32,
+    pub fall_sfx: String,
+    pub spark_color: Vec4,
+    /// image to draw instead of the LDtk entity's tile
+    pub sprite: Option<String>,
+    /// name of an AI personality; orbs without one just drift
+    pub personality: Option<String>,
+    pub thrust: f32,
+    pub brake: f32,
+    /// radians per second
+    pub turn_rate: f32,
+    pub max_speed: f32,
+    /// able to charge up a shove
+    pub shove: bool,
+}
+
+impl Default for Archetype {
+    fn default() -> Self {
+        Archetype {
+            role: Role::Neutral,
+            seat: Seat::One,
+            mass: 1.0,
+            radius: ORB_RADIUS,
+            restitution: 1.0,
+            fall_sfx: "enemy-fall.ogg".into(),
+            spark_color: Vec4::new(1.0, 0.1, 0.1, 1.0),
+            sprite: None,
+            personality: None,
+            thrust: movement::ACCEL_V,
+            brake: -movement::DECEL_V,
+            turn_rate: movement::TURN_RATE,
+            max_speed: movement::MAX_V,
+            shove: false,
+        }
+    }
+}
+
+impl Archetype {
+    pub fn handling(&self) -> movement::Handling {
+        movement::Handling {
+            accel: self.thrust,
+            decel: -self.brake,
+            turn_rate: self.turn_rate,
+            max_speed: self.max_speed,
+        }
+    }
+
+    // any field set on the entity in LDtk takes precedence over its archetype
+    fn override_from(&mut self, instance: &EntityInstance) {
+        let float = |name: &str| instance.get_float_field(name).ok().copied();
+        let text = |name: &str| {
+            instance
+                .get_string_field(name)
+                .or_else(|_| instance.get_enum_field(name))
+                .or_else(|_| instance.get_file_path_field(name))
+                .ok()
+                .cloned()
+        };
+
+        if let Some(role) = text("role") {
+            match role.to_lowercase().as_str() {
+                "player" => self.role = Role::Player,
+                "enemy" => self.role = Role::Enemy,
+                "neutral" => self.role = Role::Neutral,
+                _ => warn!("{} has unknown role '{role}'", instance.identifier),
+            }
+        }
+
+        if let Some(mass) = float("mass") {
+            self.mass = mass;
+        }
+
+        if let Some(radius) = float("radius") {
+            self.radius = radius;
+        }
+
+        if let Some(restitution) = float("restitution") {
+            self.restitution = restitution;
+        }
+
+        if let Some(fall_sfx) = text("fall_sfx") {
+            self.fall_sfx = fall_sfx;
+        }
+
+        if let Ok(color) = instance.get_color_field("spark_color") {
+            self.spark_color = Vec4::from(color.as_rgba_f32());
+        }
+
+        if let Some(sprite) = text("sprite") {
+            self.sprite = Some(sprite);
+        }
+
+        if let Some(personality) = text("personality") {
+            self.personality = Some(personality);
+        }
+
+        if let Some(thrust) = float("thrust") {
+            self.thrust = thrust;
+        }
+
+        if let Some(brake) = float("brake") {
+            self.brake = brake;
+        }
+
+        if let Some(turn_rate) = float("turn_rate") {
+            self.turn_rate = turn_rate;
+        }
+
+        if let Some(max_speed) = float("max_speed") {
+            self.max_speed = max_speed;
+        }
+
+        if let Ok(shove) = instance.get_bool_field("shove") {
+            self.shove = *shove;
+        }
+    }
+}
+
+/// Named archetypes, loaded from a .archetypes.ron file
+#[derive(Deserialize, TypeUuid, TypePath, Debug)]
+#[uuid = "5b6a2c3e-8f41-4d0a-9a8e-3c27f1d4b6e9"]
+pub struct Archetypes(HashMap<String, Archetype>);
+
+impl Archetypes {
+    /// The entity's archetype - named by its `archetype` field, or else by its identifier -
+    /// with the entity's own fields applied on top
+    pub fn resolve(&self, instance: &EntityInstance) -> Archetype {
+        let name = instance
+            .get_string_field("archetype")
+            .unwrap_or(&instance.identifier);
+
+        let mut archetype = match self.0.get(name) {
+            Some(archetype) => archetype.clone(),
+            None => {
+                warn!("unknown archetype '{name}'");
+                Archetype::default()
+            }
+        };
+
+        archetype.override_from(instance);
+        archetype
+    }
+}
+
+#[derive(Default)]
+struct ArchetypesLoader;
+
+impl AssetLoader for ArchetypesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let archetypes: Archetypes = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(archetypes));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["archetypes.ron"]
+    }
+}
+
+/// The archetypes every level is built from
+#[derive(Resource)]
+pub struct DefaultArchetypes(pub Handle<Archetypes>);
+
+pub fn archetypes_loaded(
+    defaults: Res<DefaultArchetypes>,
+    assets: Res<Assets<Archetypes>>,
+) -> bool {
+    assets.contains(&defaults.0)
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(DefaultArchetypes(asset_server.load("orbs.archetypes.ron")));
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.add_asset::<Archetypes>()
+            .init_asset_loader::<ArchetypesLoader>()
+            .add_systems(Startup, setup);
+    })
+}
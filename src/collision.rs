@@ -1,7 +1,7 @@
-use bevy::{ecs::system::SystemParam, prelude::*, utils::HashSet};
-use bevy_rapier2d::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*, transform::TransformSystem, utils::HashSet};
+use bevy_rapier2d::{plugin::systems::sync_removals, prelude::*};
 
-use crate::{AppState, CacheEvent, InteractionEvent, OpaquePlugin, Orb, Tile};
+use crate::{AppState, CacheEvent, InteractionEvent, OpaquePlugin, Orb, TickSet, Tile, TIMESTEP};
 
 const GROUP_ONLY_ALL: Group = Group::from_bits_truncate(1 << 31);
 const GROUP_WALL: Group = Group::from_bits_truncate(0b0001);
@@ -26,6 +26,13 @@ struct Intangible {
     frames: u8,
 }
 
+/// Physics pose at the last two ticks, blended between for rendering
+#[derive(Component, Default)]
+pub struct Interpolated {
+    previous: Option<(Vec3, Quat)>,
+    current: Option<(Vec3, Quat)>,
+}
+
 #[derive(SystemParam)]
 struct Hooks<'w, 's> {
     intangibles: Query<'w, 's, &'static Intangible>,
@@ -45,6 +52,50 @@ impl BevyPhysicsHooks for Hooks<'_, '_> {
 
 fn setup(mut rapier: ResMut<RapierConfiguration>) {
     rapier.gravity = Vec2::ZERO;
+    rapier.timestep_mode = TimestepMode::Fixed {
+        dt: TIMESTEP.as_secs_f32(),
+        substeps: 1,
+    };
+}
+
+// the world stands still outside of play, e.g. while a menu is open
+fn enable_physics(enable: bool) -> impl Fn(ResMut<RapierConfiguration>) {
+    move |mut rapier| {
+        rapier.physics_pipeline_active = enable;
+    }
+}
+
+// undo the last frame's blend before any ticks, so that gameplay and physics see the real pose
+fn restore_physics_pose(mut query: Query<(&mut Transform, &Interpolated)>) {
+    for (mut transform, interpolated) in query.iter_mut() {
+        if let Some((translation, rotation)) = interpolated.current {
+            if transform.translation != translation || transform.rotation != rotation {
+                transform.translation = translation;
+                transform.rotation = rotation;
+            }
+        }
+    }
+}
+
+fn record_physics_pose(mut query: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in query.iter_mut() {
+        let pose = (transform.translation, transform.rotation);
+        interpolated.previous = interpolated.current.or(Some(pose));
+        interpolated.current = Some(pose);
+    }
+}
+
+fn interpolate_physics_pose(
+    time: Res<FixedTime>,
+    mut query: Query<(&mut Transform, &Interpolated)>,
+) {
+    let alpha = time.accumulated().as_secs_f32() / time.period.as_secs_f32();
+    for (mut transform, interpolated) in query.iter_mut() {
+        if let (Some(previous), Some(current)) = (interpolated.previous, interpolated.current) {
+            transform.translation = previous.0.lerp(current.0, alpha);
+            transform.rotation = previous.1.slerp(current.1, alpha);
+        }
+    }
 }
 
 fn cache_collider_hierarchy(
@@ -55,8 +106,7 @@ fn cache_collider_hierarchy(
 ) {
     if input
         .iter()
-        .map(|event| matches!(event, CacheEvent::InvalidateColliderHierarchy))
-        .fold(false, |acc, x| acc || x)
+        .any(|event| matches!(event, CacheEvent::InvalidateColliderHierarchy))
     {
         for (children, tile) in tiles.iter() {
             match tile {
@@ -141,19 +191,53 @@ fn become_tangible(mut commands: Commands, mut query: Query<(Entity, &mut Intang
 pub fn plugin() -> impl Plugin {
     OpaquePlugin(|app| {
         app.add_plugins(
-            RapierPhysicsPlugin::<Hooks>::pixels_per_meter(100.0),
+            RapierPhysicsPlugin::<Hooks>::pixels_per_meter(100.0).with_default_system_setup(false),
             //RapierDebugRenderPlugin::default(),
         )
+        .configure_sets(
+            FixedUpdate,
+            (
+                PhysicsSet::SyncBackend,
+                PhysicsSet::StepSimulation,
+                PhysicsSet::Writeback,
+            )
+                .chain(),
+        )
         .add_systems(Startup, setup)
+        .add_systems(OnEnter(AppState::Playing), enable_physics(true))
+        .add_systems(OnExit(AppState::Playing), enable_physics(false))
+        .add_systems(PreUpdate, restore_physics_pose)
         .add_systems(
-            Update,
+            FixedUpdate,
             (
-                detect_collisions.before(super::trigger_interaction),
-                become_tangible,
-            )
-                .run_if(in_state(AppState::Playing)),
+                RapierPhysicsPlugin::<Hooks>::get_systems(PhysicsSet::SyncBackend)
+                    .in_set(PhysicsSet::SyncBackend),
+                RapierPhysicsPlugin::<Hooks>::get_systems(PhysicsSet::StepSimulation)
+                    .in_set(PhysicsSet::StepSimulation),
+                RapierPhysicsPlugin::<Hooks>::get_systems(PhysicsSet::Writeback)
+                    .in_set(PhysicsSet::Writeback),
+                record_physics_pose.after(PhysicsSet::Writeback),
+                (
+                    detect_collisions
+                        .before(super::play_interaction_sfx)
+                        .before(super::trigger_interaction),
+                    become_tangible,
+                )
+                    .in_set(TickSet::Interaction)
+                    .run_if(in_state(AppState::Playing)),
+            ),
+        )
+        .add_systems(
+            PostUpdate,
+            (
+                cache_collider_hierarchy,
+                interpolate_physics_pose
+                    .run_if(in_state(AppState::Playing))
+                    .before(TransformSystem::TransformPropagate),
+                // removals are only buffered for two frames, which can pass without a tick
+                sync_removals,
+            ),
         )
-        .add_systems(PostUpdate, cache_collider_hierarchy)
         .insert_resource(ColliderEntities {
             wall_colliders: HashSet::new(),
             pit_colliders: HashSet::new(),
@@ -162,6 +246,14 @@ pub fn plugin() -> impl Plugin {
     })
 }
 
+/// Whether there's no wall between two points in world space; orbs and pits don't block the view
+pub fn line_of_sight(rapier: &RapierContext, from: Vec2, to: Vec2) -> bool {
+    let filter = QueryFilter::new().groups(CollisionGroups::new(FILTER_ALL, GROUP_WALL));
+    rapier
+        .cast_ray(from, to - from, 1.0, true, filter)
+        .is_none()
+}
+
 // XXX surely there is a builtin version of this
 pub struct Rect {
     pub origin: Vec2,
@@ -200,13 +292,13 @@ pub fn spawn_pit_wall(children: &mut ChildBuilder, rect: &Rect) {
         .insert(ActiveHooks::FILTER_CONTACT_PAIRS);
 }
 
-pub fn spawn_orb(children: &mut ChildBuilder, mass: f32) {
+pub fn spawn_orb(children: &mut ChildBuilder, mass: f32, radius: f32, restitution: f32) {
     children
-        .spawn(Collider::ball(100.0))
+        .spawn(Collider::ball(radius))
         .insert(CollisionGroups::new(GROUP_ORB, FILTER_MAIN))
         .insert(ColliderMassProperties::Mass(mass))
         .insert(Restitution {
-            coefficient: 1.0,
+            coefficient: restitution,
             combine_rule: CoefficientCombineRule::Min,
         })
         .insert(ActiveEvents::COLLISION_EVENTS);
@@ -217,9 +309,9 @@ pub fn spawn_orb(children: &mut ChildBuilder, mass: f32) {
         .insert(ActiveEvents::COLLISION_EVENTS);
 }
 
-pub fn spawn_falling_orb(children: &mut ChildBuilder) {
+pub fn spawn_falling_orb(children: &mut ChildBuilder, radius: f32) {
     children
-        .spawn(Collider::ball(100.0))
+        .spawn(Collider::ball(radius))
         .insert(CollisionGroups::new(GROUP_ONLY_ALL, FILTER_WALLS))
         .insert(ColliderMassProperties::Mass(1.0))
         .insert(Restitution::coefficient(1.0))
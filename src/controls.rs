@@ -0,0 +1,635 @@
+use anyhow::Context;
+use bevy::{math::Vec3Swizzles, prelude::*, window::PrimaryWindow};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{
+    menu::{self, MenuAction, MenuEvent, MenuLocked},
+    replay, versus, AppState, InputEvent, Intent, OpaquePlugin, Orb, PlayerInput, TickSet,
+};
+
+// pointer distance from the player at which thrust is at full strength
+const POINTER_RANGE: f32 = 256.0;
+
+/// Something the player can do, bound to any number of keys and buttons
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Brake,
+    Shove,
+    Rewind,
+}
+
+impl Action {
+    const ALL: [Action; 7] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Brake,
+        Action::Shove,
+        Action::Rewind,
+    ];
+
+    /// everything but rewinding, which would undo the other player's moves too
+    const VERSUS: [Action; 6] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Brake,
+        Action::Shove,
+    ];
+}
+
+/// Which of the local players an input or orb belongs to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Seat {
+    #[default]
+    One,
+    Two,
+}
+
+impl Seat {
+    pub const COUNT: usize = 2;
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn number(self) -> usize {
+        self.index() + 1
+    }
+}
+
+/// Which physical inputs map to each action, persisted in the user's config dir
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct Bindings {
+    keys: BTreeMap<Action, Vec<KeyCode>>,
+    buttons: BTreeMap<Action, Vec<GamepadButtonType>>,
+    /// the second seat's share of the keyboard in versus; gamepads use the same buttons as the
+    /// first seat, since each player has their own
+    #[serde(default)]
+    versus_keys: BTreeMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            keys: BTreeMap::from([
+                (Action::Up, vec![KeyCode::Up, KeyCode::W]),
+                (Action::Down, vec![KeyCode::Down, KeyCode::S]),
+                (Action::Left, vec![KeyCode::Left, KeyCode::A]),
+                (Action::Right, vec![KeyCode::Right, KeyCode::D]),
+                (Action::Brake, vec![KeyCode::Space]),
+                (Action::Shove, vec![KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+                (Action::Rewind, vec![KeyCode::Back, KeyCode::Z]),
+            ]),
+            buttons: BTreeMap::from([
+                (Action::Up, vec![GamepadButtonType::DPadUp]),
+                (Action::Down, vec![GamepadButtonType::DPadDown]),
+                (Action::Left, vec![GamepadButtonType::DPadLeft]),
+                (Action::Right, vec![GamepadButtonType::DPadRight]),
+                (
+                    Action::Brake,
+                    vec![GamepadButtonType::South, GamepadButtonType::RightTrigger2],
+                ),
+                (
+                    Action::Shove,
+                    vec![GamepadButtonType::West, GamepadButtonType::LeftTrigger2],
+                ),
+                (
+                    Action::Rewind,
+                    vec![GamepadButtonType::North, GamepadButtonType::LeftTrigger],
+                ),
+            ]),
+            versus_keys: BTreeMap::from([
+                (Action::Up, vec![KeyCode::Up]),
+                (Action::Down, vec![KeyCode::Down]),
+                (Action::Left, vec![KeyCode::Left]),
+                (Action::Right, vec![KeyCode::Right]),
+                (Action::Brake, vec![KeyCode::ControlRight, KeyCode::Return]),
+                (Action::Shove, vec![KeyCode::ShiftRight]),
+            ]),
+        }
+    }
+}
+
+impl Bindings {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("shoveit").join("controls.ron"))
+    }
+
+    /// Saved bindings, with defaults for any actions they don't mention
+    fn load() -> Bindings {
+        let defaults = Bindings::default();
+
+        let Some(path) = Bindings::path() else {
+            return defaults;
+        };
+
+        let mut bindings = match std::fs::read_to_string(&path) {
+            Ok(text) => match ron::from_str::<Bindings>(&text) {
+                Ok(bindings) => bindings,
+                Err(cause) => {
+                    warn!("ignoring {}: {}", path.display(), cause);
+                    return defaults;
+                }
+            },
+            Err(_) => return defaults,
+        };
+
+        for (action, keys) in defaults.keys {
+            bindings.keys.entry(action).or_insert(keys);
+        }
+
+        for (action, buttons) in defaults.buttons {
+            bindings.buttons.entry(action).or_insert(buttons);
+        }
+
+        for (action, keys) in defaults.versus_keys {
+            bindings.versus_keys.entry(action).or_insert(keys);
+        }
+
+        bindings
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = Bindings::path().context("no config directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context("create config directory")?;
+        }
+
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .context("serialise Bindings")?;
+        std::fs::write(&path, text).with_context(|| format!("write {}", path.display()))?;
+        Ok(())
+    }
+
+    fn seat_keys(&self, seat: Seat) -> &BTreeMap<Action, Vec<KeyCode>> {
+        match seat {
+            Seat::One => &self.keys,
+            Seat::Two => &self.versus_keys,
+        }
+    }
+
+    /// While the keyboard is shared, keys bound for the second seat are taken from the first
+    pub fn key_pressed(
+        &self,
+        input: &Input<KeyCode>,
+        seat: Seat,
+        shared: bool,
+        action: Action,
+    ) -> bool {
+        let taken = |key: &KeyCode| {
+            shared && seat == Seat::One && self.versus_keys.values().flatten().any(|k| k == key)
+        };
+
+        self.seat_keys(seat)
+            .get(&action)
+            .is_some_and(|keys| keys.iter().any(|key| input.pressed(*key) && !taken(key)))
+    }
+
+    pub fn button_pressed(
+        &self,
+        input: &Input<GamepadButton>,
+        gamepad: Gamepad,
+        action: Action,
+    ) -> bool {
+        self.buttons.get(&action).is_some_and(|buttons| {
+            buttons
+                .iter()
+                .any(|button_type| input.pressed(GamepadButton::new(gamepad, *button_type)))
+        })
+    }
+
+    // each key does one thing, so taking it for this action removes it from the others; the
+    // first seat's keys are taken from the second, which would otherwise win in versus
+    fn rebind_key(&mut self, seat: Seat, action: Action, key: KeyCode) {
+        if seat == Seat::One {
+            for keys in self.versus_keys.values_mut() {
+                keys.retain(|k| *k != key);
+            }
+        }
+
+        let seat_keys = match seat {
+            Seat::One => &mut self.keys,
+            Seat::Two => &mut self.versus_keys,
+        };
+        for keys in seat_keys.values_mut() {
+            keys.retain(|k| *k != key);
+        }
+        seat_keys.insert(action, vec![key]);
+    }
+
+    fn rebind_button(&mut self, action: Action, button: GamepadButtonType) {
+        for buttons in self.buttons.values_mut() {
+            buttons.retain(|b| *b != button);
+        }
+        self.buttons.insert(action, vec![button]);
+    }
+
+    fn describe(&self, seat: Seat, action: Action) -> String {
+        let keys = self.seat_keys(seat).get(&action).into_iter().flatten();
+        let buttons = match seat {
+            Seat::One => self.buttons.get(&action),
+            Seat::Two => None,
+        };
+        let names: Vec<String> = keys
+            .map(|key| format!("{key:?}"))
+            .chain(
+                buttons
+                    .into_iter()
+                    .flatten()
+                    .map(|button| format!("{button:?}")),
+            )
+            .collect();
+
+        let label = match seat {
+            Seat::One => format!("{action:?}"),
+            Seat::Two => format!("P2 {action:?}"),
+        };
+
+        if names.is_empty() {
+            format!("{label}: -")
+        } else {
+            format!("{label}: {}", names.join(" / "))
+        }
+    }
+}
+
+/// Marks the rebinding screen's menu
+#[derive(Component)]
+struct ControlsScreen;
+
+/// The state to go back to when leaving the rebinding screen
+#[derive(Resource)]
+pub struct ControlsReturn(pub AppState);
+
+/// Waiting for the player to press something for an action
+#[derive(Resource)]
+struct AwaitingBinding(Seat, Action);
+
+fn keyboard_input(
+    bindings: Res<Bindings>,
+    input: Res<Input<KeyCode>>,
+    versus: Option<Res<versus::Versus>>,
+    mut events: EventWriter<InputEvent>,
+) {
+    // in versus the keyboard is split between both seats
+    let seats: &[Seat] = if versus.is_some() {
+        &[Seat::One, Seat::Two]
+    } else {
+        &[Seat::One]
+    };
+
+    for &seat in seats {
+        let pressed = |action| bindings.key_pressed(&input, seat, versus.is_some(), action);
+
+        // a shove can be charged while doing anything else
+        if pressed(Action::Shove) {
+            events.send(InputEvent::new(seat, Intent::Charge));
+        }
+
+        if pressed(Action::Rewind) {
+            events.send(InputEvent::new(seat, Intent::Rewind));
+        }
+
+        // braking takes priority
+        if pressed(Action::Brake) {
+            events.send(InputEvent::new(seat, Intent::Decelerate));
+            continue;
+        }
+
+        // if not braking, we may thrust
+        let mut thrust = Vec2::ZERO;
+
+        if pressed(Action::Right) {
+            thrust.x += 1.0;
+        }
+
+        if pressed(Action::Left) {
+            thrust.x -= 1.0;
+        }
+
+        if pressed(Action::Up) {
+            thrust.y += 1.0;
+        }
+
+        if pressed(Action::Down) {
+            thrust.y -= 1.0;
+        }
+
+        if thrust != Vec2::ZERO {
+            thrust = thrust.normalize();
+            events.send(InputEvent::new(seat, Intent::Accelerate(thrust)));
+        }
+    }
+}
+
+fn gamepad_input(
+    bindings: Res<Bindings>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    versus: Option<Res<versus::Versus>>,
+    mut events: EventWriter<InputEvent>,
+) {
+    // gamepads come and go; any that are currently connected can steer, except that in versus
+    // the first two each get a seat of their own
+    let mut connected: Vec<Gamepad> = gamepads.iter().collect();
+    connected.sort_by_key(|gamepad| gamepad.id);
+
+    for (index, gamepad) in connected.into_iter().enumerate() {
+        let seat = match (versus.is_some(), index) {
+            (false, _) | (true, 0) => Seat::One,
+            (true, 1) => Seat::Two,
+            (true, _) => continue,
+        };
+
+        let pressed = |action| bindings.button_pressed(&buttons, gamepad, action);
+        let axis = |axis_type| {
+            axes.get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+
+        // a shove can be charged while doing anything else
+        if pressed(Action::Shove) {
+            events.send(InputEvent::new(seat, Intent::Charge));
+        }
+
+        if pressed(Action::Rewind) {
+            events.send(InputEvent::new(seat, Intent::Rewind));
+        }
+
+        // braking takes priority
+        if pressed(Action::Brake) {
+            events.send(InputEvent::new(seat, Intent::Decelerate));
+            continue;
+        }
+
+        // buttons are digital, like the keyboard
+        let mut digital = Vec2::ZERO;
+
+        if pressed(Action::Right) {
+            digital.x += 1.0;
+        }
+
+        if pressed(Action::Left) {
+            digital.x -= 1.0;
+        }
+
+        if pressed(Action::Up) {
+            digital.y += 1.0;
+        }
+
+        if pressed(Action::Down) {
+            digital.y -= 1.0;
+        }
+
+        // the stick is analog, thrusting partially until pushed all the way
+        let stick = Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+
+        let thrust = if digital != Vec2::ZERO {
+            digital.normalize()
+        } else {
+            stick.clamp_length_max(1.0)
+        };
+
+        if thrust != Vec2::ZERO {
+            events.send(InputEvent::new(seat, Intent::Accelerate(thrust)));
+        }
+    }
+}
+
+// left mouse or a single touch thrusts towards the pointer; right mouse or a second touch brakes;
+// middle mouse charges a shove. the pointer always steers the first seat
+fn pointer_input(
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    players: Query<(&Transform, &PlayerInput), With<Orb>>,
+    mut events: EventWriter<InputEvent>,
+) {
+    let touch_count = touches.iter().count();
+
+    if mouse.pressed(MouseButton::Middle) {
+        events.send(InputEvent::new(Seat::One, Intent::Charge));
+    }
+
+    // braking takes priority
+    if mouse.pressed(MouseButton::Right) || touch_count >= 2 {
+        events.send(InputEvent::new(Seat::One, Intent::Decelerate));
+        return;
+    }
+
+    let pointer = if mouse.pressed(MouseButton::Left) {
+        windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+    } else {
+        touches.first_pressed_position()
+    };
+
+    let player = players
+        .iter()
+        .find(|(_, input)| input.0 == Seat::One)
+        .map(|(transform, _)| transform);
+
+    let (Some(pointer), Ok((camera, camera_transform)), Some(player)) =
+        (pointer, cameras.get_single(), player)
+    else {
+        return;
+    };
+
+    let Some(target) = camera.viewport_to_world_2d(camera_transform, pointer) else {
+        return;
+    };
+
+    // near the orb, thrust tapers off so that small nudges are possible
+    let offset = target - player.translation.xy();
+    let thrust = offset.normalize_or_zero() * (offset.length() / POINTER_RANGE).min(1.0);
+
+    if thrust != Vec2::ZERO {
+        events.send(InputEvent::new(Seat::One, Intent::Accelerate(thrust)));
+    }
+}
+
+fn open_controls(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if input.just_pressed(KeyCode::F1) {
+        commands.insert_resource(ControlsReturn(*state.get()));
+        next_state.set(AppState::Controls);
+    }
+}
+
+fn spawn_controls(commands: &mut Commands, bindings: &Bindings, selected: usize) {
+    let mut items: Vec<(String, MenuAction)> = Action::ALL
+        .iter()
+        .map(|action| (Seat::One, *action))
+        .chain(Action::VERSUS.iter().map(|action| (Seat::Two, *action)))
+        .map(|(seat, action)| {
+            (
+                bindings.describe(seat, action),
+                MenuAction::Rebind(seat, action),
+            )
+        })
+        .collect();
+    items.push(("Reset to defaults".into(), MenuAction::ResetControls));
+    items.push(("Back".into(), MenuAction::Back));
+
+    let menu = menu::spawn_menu(commands, "Controls", items, selected);
+    commands.entity(menu).insert(ControlsScreen);
+}
+
+fn enter_controls(mut commands: Commands, bindings: Res<Bindings>) {
+    spawn_controls(&mut commands, &bindings, 0);
+}
+
+fn exit_controls(mut commands: Commands) {
+    commands.remove_resource::<AwaitingBinding>();
+    commands.remove_resource::<MenuLocked>();
+}
+
+fn handle_controls_menu(
+    mut commands: Commands,
+    mut events: EventReader<MenuEvent>,
+    mut bindings: ResMut<Bindings>,
+    mut next_state: ResMut<NextState<AppState>>,
+    back_to: Option<Res<ControlsReturn>>,
+    menus: Query<(Entity, &menu::Menu), With<ControlsScreen>>,
+) -> anyhow::Result<()> {
+    for MenuEvent(action) in events.iter() {
+        match *action {
+            MenuAction::Back => {
+                next_state.set(back_to.as_ref().map_or(AppState::Playing, |state| state.0));
+            }
+            MenuAction::Rebind(seat, action) => {
+                for (entity, _) in menus.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+
+                let prompt = menu::spawn_menu(
+                    &mut commands,
+                    "Controls",
+                    vec![(
+                        match seat {
+                            Seat::One => format!("Press a key or button for {action:?}"),
+                            Seat::Two => format!("Press a key for P2 {action:?}"),
+                        },
+                        MenuAction::Back,
+                    )],
+                    0,
+                );
+                commands.entity(prompt).insert(ControlsScreen);
+                commands.insert_resource(AwaitingBinding(seat, action));
+                commands.insert_resource(MenuLocked);
+            }
+            MenuAction::ResetControls => {
+                *bindings = Bindings::default();
+                bindings.save()?;
+
+                for (entity, menu) in menus.iter() {
+                    commands.entity(entity).despawn_recursive();
+                    spawn_controls(&mut commands, &bindings, menu.selected());
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn capture_binding(
+    mut commands: Commands,
+    awaiting: Res<AwaitingBinding>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut bindings: ResMut<Bindings>,
+    menus: Query<Entity, With<ControlsScreen>>,
+) -> anyhow::Result<()> {
+    let AwaitingBinding(seat, action) = *awaiting;
+
+    // escape cancels, rather than binding itself; the second seat's buttons are the first's
+    let (captured, rebound) = if let Some(key) = keys.get_just_pressed().next() {
+        if *key == KeyCode::Escape {
+            (true, false)
+        } else {
+            bindings.rebind_key(seat, action, *key);
+            (true, true)
+        }
+    } else if let (Seat::One, Some(button)) = (seat, buttons.get_just_pressed().next()) {
+        bindings.rebind_button(action, button.button_type);
+        (true, true)
+    } else {
+        (false, false)
+    };
+
+    if captured {
+        for entity in menus.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let selected = match seat {
+            Seat::One => Action::ALL.iter().position(|a| *a == action),
+            Seat::Two => Action::VERSUS
+                .iter()
+                .position(|a| *a == action)
+                .map(|index| Action::ALL.len() + index),
+        };
+        spawn_controls(&mut commands, &bindings, selected.unwrap_or(0));
+        commands.remove_resource::<AwaitingBinding>();
+        commands.remove_resource::<MenuLocked>();
+    }
+
+    if rebound {
+        bindings.save()?;
+    }
+
+    Ok(())
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.insert_resource(Bindings::load())
+            .add_systems(
+                FixedUpdate,
+                (keyboard_input, gamepad_input, pointer_input)
+                    .run_if(not(resource_exists::<replay::Playback>()))
+                    .run_if(in_state(AppState::Playing))
+                    .in_set(TickSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    open_controls.run_if(in_state(AppState::Playing)),
+                    (
+                        handle_controls_menu
+                            .pipe(super::handle)
+                            .run_if(not(resource_exists::<AwaitingBinding>())),
+                        capture_binding
+                            .pipe(super::handle)
+                            .run_if(resource_exists::<AwaitingBinding>()),
+                    )
+                        .run_if(in_state(AppState::Controls)),
+                ),
+            )
+            .add_systems(OnEnter(AppState::Controls), enter_controls)
+            .add_systems(
+                OnExit(AppState::Controls),
+                (menu::despawn_menus::<ControlsScreen>, exit_controls),
+            );
+    })
+}
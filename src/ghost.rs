@@ -0,0 +1,247 @@
+use anyhow::Context;
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::{
+    collision, save, AppState, Attempt, OpaquePlugin, OutcomeEvent, PlayerInput, Tick, TickSet,
+};
+
+// bump when the meaning of existing fields changes
+const GHOST_VERSION: u32 = 1;
+
+const GHOST_ALPHA: f32 = 0.35;
+
+/// The player's position at the end of each tick of one clear of a level
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Run {
+    version: u32,
+    positions: Vec<[f32; 2]>,
+}
+
+/// Best clears, persisted in the user's data dir unless this is an unattended run
+#[derive(Resource)]
+struct Ghosts {
+    dir: Option<PathBuf>,
+    /// keyed by level iid; None if there is no clear on record
+    runs: HashMap<String, Option<Run>>,
+}
+
+impl Ghosts {
+    fn new(persistent: bool) -> Self {
+        Ghosts {
+            dir: persistent
+                .then(|| dirs::data_dir().map(|dir| dir.join("shoveit").join("ghosts")))
+                .flatten(),
+            runs: HashMap::new(),
+        }
+    }
+
+    fn path(&self, iid: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(iid).with_extension("json"))
+    }
+
+    // loaded on first use, since most levels won't be played in any one session
+    fn get(&mut self, iid: &str) -> Option<&Run> {
+        if !self.runs.contains_key(iid) {
+            let run = self.path(iid).and_then(|path| {
+                let text = std::fs::read_to_string(&path).ok()?;
+                match serde_json::from_str::<Run>(&text) {
+                    Ok(run) if run.version > GHOST_VERSION => None,
+                    Ok(run) => Some(run),
+                    Err(cause) => {
+                        warn!("ignoring {}: {}", path.display(), cause);
+                        None
+                    }
+                }
+            });
+            self.runs.insert(iid.to_owned(), run);
+        }
+
+        self.runs.get(iid).and_then(Option::as_ref)
+    }
+
+    fn save(&self, iid: &str, run: &Run) -> anyhow::Result<()> {
+        let Some(path) = self.path(iid) else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context("create ghost directory")?;
+        }
+
+        save::write_atomically(&path, |writer| {
+            serde_json::to_writer(writer, run)
+                .with_context(|| format!("serialise ghost {}", path.display()))
+        })
+    }
+}
+
+/// The attempt in progress, recorded in case it turns out to be a new best
+#[derive(Resource, Default)]
+struct Lap(Vec<Vec2>);
+
+/// The best clear of the current level, being played back alongside it
+#[derive(Resource, Default)]
+struct Pace(Option<Vec<Vec2>>);
+
+/// Translucent stand-in for the player's best clear; it has no collider, so never interacts
+#[derive(Component)]
+struct Ghost;
+
+fn restart_lap(
+    mut commands: Commands,
+    mut ghosts: ResMut<Ghosts>,
+    mut lap: ResMut<Lap>,
+    mut pace: ResMut<Pace>,
+    mut level_events: EventReader<LevelEvent>,
+    existing: Query<Entity, With<Ghost>>,
+) {
+    for level_event in level_events.iter() {
+        if let LevelEvent::Spawned(iid) = level_event {
+            lap.0.clear();
+            pace.0 = ghosts.get(iid).map(|run| {
+                run.positions
+                    .iter()
+                    .copied()
+                    .map(Vec2::from_array)
+                    .collect()
+            });
+
+            for ghost in existing.iter() {
+                commands.entity(ghost).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn record_lap(mut lap: ResMut<Lap>, players: Query<&Transform, With<PlayerInput>>) {
+    if let Ok(transform) = players.get_single() {
+        lap.0.push(transform.translation.xy());
+    }
+}
+
+// a copy of the player's sprite, placed alongside it so that their positions mean the same thing
+#[allow(clippy::type_complexity)]
+fn spawn_ghost(
+    mut commands: Commands,
+    pace: Res<Pace>,
+    ghosts: Query<(), With<Ghost>>,
+    players: Query<
+        (
+            &Parent,
+            &Transform,
+            Option<&TextureAtlasSprite>,
+            Option<&Handle<TextureAtlas>>,
+            Option<&Sprite>,
+            Option<&Handle<Image>>,
+        ),
+        With<PlayerInput>,
+    >,
+) {
+    if pace.0.is_none() || !ghosts.is_empty() {
+        return;
+    }
+
+    let Ok((parent, transform, atlas_sprite, atlas, sprite, image)) = players.get_single() else {
+        return;
+    };
+
+    let mut ghost = commands.spawn((
+        Ghost,
+        SpatialBundle::from_transform(Transform::from_translation(transform.translation - Vec3::Z)),
+        collision::Interpolated::default(),
+    ));
+
+    if let (Some(sprite), Some(atlas)) = (atlas_sprite, atlas) {
+        let mut sprite = sprite.clone();
+        sprite.color.set_a(GHOST_ALPHA);
+        ghost.insert((sprite, atlas.clone()));
+    } else if let (Some(sprite), Some(image)) = (sprite, image) {
+        let mut sprite = sprite.clone();
+        sprite.color.set_a(GHOST_ALPHA);
+        ghost.insert((sprite, image.clone()));
+    }
+
+    ghost.set_parent(parent.get());
+}
+
+// the ghost vanishes once its run is over, since it would have won the level by then
+fn move_ghost(
+    tick: Res<Tick>,
+    pace: Res<Pace>,
+    mut ghosts: Query<(&mut Transform, &mut Visibility), With<Ghost>>,
+) {
+    let Some(positions) = &pace.0 else {
+        return;
+    };
+
+    for (mut transform, mut visibility) in ghosts.iter_mut() {
+        match positions.get(tick.0.saturating_sub(1) as usize) {
+            Some(position) => {
+                transform.translation = position.extend(transform.translation.z);
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+// a clear in fewer ticks replaces the ghost, even if it took more thrusts
+fn save_ghost(
+    attempt: Res<Attempt>,
+    lap: Res<Lap>,
+    mut ghosts: ResMut<Ghosts>,
+    mut pace: ResMut<Pace>,
+    mut outcomes: EventReader<OutcomeEvent>,
+) -> anyhow::Result<()> {
+    if !outcomes
+        .iter()
+        .any(|outcome| matches!(outcome, OutcomeEvent::Victory))
+    {
+        return Ok(());
+    }
+
+    if ghosts
+        .get(&attempt.level)
+        .is_some_and(|best| best.positions.len() <= lap.0.len())
+    {
+        return Ok(());
+    }
+
+    let run = Run {
+        version: GHOST_VERSION,
+        positions: lap.0.iter().map(|position| position.to_array()).collect(),
+    };
+    ghosts.save(&attempt.level, &run)?;
+    ghosts.runs.insert(attempt.level.clone(), Some(run));
+    pace.0 = Some(lap.0.clone());
+
+    Ok(())
+}
+
+pub fn plugin(persistent: bool) -> impl Plugin {
+    OpaquePlugin(move |app| {
+        app.insert_resource(Ghosts::new(persistent))
+            .init_resource::<Lap>()
+            .init_resource::<Pace>()
+            .add_systems(Update, restart_lap)
+            .add_systems(
+                FixedUpdate,
+                (
+                    (spawn_ghost, apply_deferred, move_ghost)
+                        .chain()
+                        .in_set(TickSet::Movement),
+                    record_lap
+                        .after(PhysicsSet::Writeback)
+                        .before(TickSet::Outcome),
+                    save_ghost.pipe(super::handle).after(TickSet::Outcome),
+                )
+                    .run_if(in_state(AppState::Playing)),
+            );
+    })
+}
@@ -0,0 +1,127 @@
+use bevy::{
+    app::{AppExit, PluginGroupBuilder, ScheduleRunnerPlugin},
+    prelude::*,
+    render::{settings::WgpuSettings, RenderPlugin},
+    time::TimeUpdateStrategy,
+    window::ExitCondition,
+};
+use bevy_ecs_ldtk::LevelSelection;
+use serde::Serialize;
+use std::{
+    sync::atomic::{AtomicI32, Ordering},
+    time::Duration,
+};
+
+use crate::{replay::Playback, OpaquePlugin, OutcomeEvent, Tick, TIMESTEP};
+
+// simulated time after which an unresolved level is abandoned
+const TIMEOUT: Duration = Duration::from_secs(600);
+
+// for runs that end without reporting anything, e.g. when asked for a level that doesn't exist
+const NO_OUTCOME: i32 = 4;
+
+static EXIT_CODE: AtomicI32 = AtomicI32::new(NO_OUTCOME);
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Victory,
+    Defeat,
+    Timeout,
+}
+
+/// Printed to stdout as a single line of JSON when the simulation ends
+#[derive(Serialize)]
+struct Report {
+    /// missing if a level asked for by iid never got as far as being found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    level: Option<usize>,
+    outcome: Outcome,
+    ticks: u32,
+    seconds: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replay_matched: Option<bool>,
+}
+
+impl Report {
+    fn exit_code(&self) -> i32 {
+        match (self.outcome, self.replay_matched) {
+            (_, Some(false)) => 3,
+            (Outcome::Victory, _) => 0,
+            (Outcome::Defeat, _) => 1,
+            (Outcome::Timeout, _) => 2,
+        }
+    }
+}
+
+fn report_outcome(
+    time: Res<Time>,
+    tick: Res<Tick>,
+    selection: Res<LevelSelection>,
+    playback: Option<Res<Playback>>,
+    mut outcomes: EventReader<OutcomeEvent>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let outcome = match outcomes.iter().next() {
+        Some(OutcomeEvent::Victory) => Outcome::Victory,
+        Some(OutcomeEvent::Defeat) => Outcome::Defeat,
+        None if time.elapsed() >= TIMEOUT => Outcome::Timeout,
+        None => return,
+    };
+
+    let report = Report {
+        level: match *selection {
+            LevelSelection::Index(index) => Some(index),
+            _ => None,
+        },
+        outcome,
+        ticks: tick.0,
+        seconds: tick.0 as f32 * TIMESTEP.as_secs_f32(),
+        replay_matched: playback.and_then(|playback| playback.matched()),
+    };
+
+    match serde_json::to_string(&report) {
+        Ok(json) => println!("{json}"),
+        Err(cause) => error!("{}", cause),
+    }
+
+    EXIT_CODE.store(report.exit_code(), Ordering::Relaxed);
+    exit.send(AppExit);
+}
+
+/// DefaultPlugins without a window, renderer backend, audio or gamepads
+pub fn plugins() -> PluginGroupBuilder {
+    DefaultPlugins
+        .set(bevy::log::LogPlugin {
+            filter: "wgpu=error,naga=warn,shoveit=info".to_string(),
+            ..default()
+        })
+        .set(RenderPlugin {
+            wgpu_settings: WgpuSettings {
+                backends: None,
+                ..default()
+            },
+        })
+        .set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        })
+        .disable::<bevy::winit::WinitPlugin>()
+        .disable::<bevy::audio::AudioPlugin>()
+        .disable::<bevy::gilrs::GilrsPlugin>()
+        .add(ScheduleRunnerPlugin::run_loop(Duration::ZERO))
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.add_systems(Update, report_outcome)
+            // exactly one tick per update, as fast as we can go
+            .insert_resource(TimeUpdateStrategy::ManualDuration(TIMESTEP));
+    })
+}
+
+/// Process exit status for the outcome reported by the last run
+pub fn exit_code() -> i32 {
+    EXIT_CODE.load(Ordering::Relaxed)
+}
@@ -0,0 +1,271 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{
+    level::{self, Enemy},
+    movement::{Fuel, Shove},
+    versus::Versus,
+    AppState, Attempt, OpaquePlugin, Orb, PlayerInput, Tick, TIMESTEP,
+};
+
+const COLOR_READY: Color = Color::WHITE;
+const COLOR_CHARGING: Color = Color::rgb(1.0, 0.9, 0.4);
+const COLOR_COOLING: Color = Color::rgb(0.4, 0.4, 0.4);
+const COLOR_EMPTY: Color = Color::rgb(1.0, 0.1, 0.1);
+
+/// Root of the overlay shown during play
+#[derive(Component)]
+struct Hud;
+
+/// A line of text describing the attempt in progress
+#[derive(Component)]
+enum Readout {
+    LevelName,
+    Enemies,
+    Time,
+    Inputs,
+}
+
+/// Hidden for players who can't shove
+#[derive(Component)]
+struct ShoveIndicator;
+
+/// Fills up while charging, and again while cooling down
+#[derive(Component)]
+struct ShoveMeter;
+
+/// Hidden on levels without a fuel budget
+#[derive(Component)]
+struct FuelIndicator;
+
+/// Empties as fuel is burned
+#[derive(Component)]
+struct FuelMeter;
+
+fn spawn_meter(
+    hud: &mut ChildBuilder,
+    label: &str,
+    indicator: impl Component,
+    meter: impl Component,
+) {
+    hud.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(8.0),
+            ..default()
+        },
+        ..default()
+    })
+    .insert(indicator)
+    .with_children(|indicator| {
+        indicator.spawn(TextBundle::from_section(
+            label,
+            TextStyle {
+                color: Color::WHITE,
+                font_size: 32.0,
+                ..default()
+            },
+        ));
+
+        indicator
+            .spawn(NodeBundle {
+                style: Style {
+                    width: Val::Px(256.0),
+                    height: Val::Px(16.0),
+                    ..default()
+                },
+                background_color: Color::rgba(1.0, 1.0, 1.0, 0.2).into(),
+                ..default()
+            })
+            .with_children(|bar| {
+                bar.spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Percent(0.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    background_color: COLOR_READY.into(),
+                    ..default()
+                })
+                .insert(meter);
+            });
+    });
+}
+
+fn setup(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(32.0),
+                top: Val::Px(32.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(Hud)
+        .with_children(|hud| {
+            for (readout, font_size) in [
+                (Readout::LevelName, 48.0),
+                (Readout::Enemies, 32.0),
+                (Readout::Time, 32.0),
+                (Readout::Inputs, 32.0),
+            ] {
+                hud.spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        color: Color::WHITE,
+                        font_size,
+                        ..default()
+                    },
+                ))
+                .insert(readout);
+            }
+        });
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(32.0),
+                bottom: Val::Px(32.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(16.0),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(Hud)
+        .with_children(|hud| {
+            spawn_meter(hud, "Fuel", FuelIndicator, FuelMeter);
+            spawn_meter(hud, "Shove", ShoveIndicator, ShoveMeter);
+        });
+}
+
+fn show_hud(show: bool) -> impl Fn(Query<&mut Visibility, With<Hud>>) {
+    move |mut huds| {
+        for mut hud in huds.iter_mut() {
+            *hud = if show {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
+
+fn update_readouts(
+    tick: Res<Tick>,
+    attempt: Res<Attempt>,
+    project: Res<level::LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+    versus: Option<Res<Versus>>,
+    enemies: Query<(), (With<Enemy>, With<Orb>)>,
+    mut readouts: Query<(&mut Text, &Readout)>,
+) {
+    for (mut text, readout) in readouts.iter_mut() {
+        let value = match readout {
+            Readout::LevelName => assets
+                .get(&project.0)
+                .and_then(|project| project.get_level(&LevelSelection::Iid(attempt.level.clone())))
+                .map(level::level_name)
+                .unwrap_or_default(),
+            // in versus, the players are each other's enemies and it's the rounds that count
+            Readout::Enemies => match &versus {
+                Some(versus) => versus.score_label(),
+                // enemies stop counting as soon as they start to fall
+                None => format!("Enemies: {}", enemies.iter().count()),
+            },
+            Readout::Time => format!("Time: {:.2}s", tick.0 as f32 * TIMESTEP.as_secs_f32()),
+            Readout::Inputs => match &versus {
+                Some(versus) => format!("Round {}", versus.round() + 1),
+                None => format!("Thrusts: {}  Brakes: {}", attempt.thrusts, attempt.brakes),
+            },
+        };
+
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+fn update_shove_meter(
+    players: Query<&Shove, With<PlayerInput>>,
+    mut indicators: Query<&mut Style, (With<ShoveIndicator>, Without<ShoveMeter>)>,
+    mut meters: Query<(&mut Style, &mut BackgroundColor), With<ShoveMeter>>,
+) {
+    let shove = players.get_single().ok();
+
+    for mut indicator in indicators.iter_mut() {
+        indicator.display = if shove.is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    let Some(shove) = shove else {
+        return;
+    };
+
+    let (level, color) = if !shove.ready() {
+        (1.0 - shove.cooldown_level(), COLOR_COOLING)
+    } else if !shove.charge.is_zero() {
+        (shove.charge_level(), COLOR_CHARGING)
+    } else {
+        (1.0, COLOR_READY)
+    };
+
+    for (mut style, mut background) in meters.iter_mut() {
+        style.width = Val::Percent(level * 100.0);
+        *background = color.into();
+    }
+}
+
+fn update_fuel_meter(
+    players: Query<&Fuel, With<PlayerInput>>,
+    mut indicators: Query<&mut Style, (With<FuelIndicator>, Without<FuelMeter>)>,
+    mut meters: Query<(&mut Style, &mut BackgroundColor), With<FuelMeter>>,
+) {
+    let fuel = players.get_single().ok();
+
+    for mut indicator in indicators.iter_mut() {
+        indicator.display = if fuel.is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    let Some(fuel) = fuel else {
+        return;
+    };
+
+    let color = if fuel.is_empty() {
+        COLOR_EMPTY
+    } else {
+        COLOR_READY
+    };
+
+    for (mut style, mut background) in meters.iter_mut() {
+        style.width = Val::Percent(fuel.level() * 100.0);
+        *background = color.into();
+    }
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (update_readouts, update_shove_meter, update_fuel_meter)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(OnEnter(AppState::Playing), show_hud(true))
+            .add_systems(OnExit(AppState::Playing), show_hud(false));
+    })
+}
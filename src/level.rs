@@ -1,6 +1,14 @@
-use crate::{ai, collision, vfx, AppState, CacheEvent, OpaquePlugin, Orb, PlayerInput, Tile};
+use crate::{
+    ai,
+    archetype::{self, Role},
+    collision,
+    controls::Seat,
+    movement, personality, results, rewind, save, versus, vfx, AppState, Attempt, CacheEvent,
+    Falling, OpaquePlugin, Orb, OutcomeEvent, PlayerInput, Tick, TickSet, Tile,
+};
 use anyhow::Context;
 use bevy::{
+    app::AppExit,
     math::Vec3Swizzles,
     prelude::*,
     sprite::Anchor,
@@ -10,10 +18,16 @@ use bevy::{
 use bevy_ecs_ldtk::prelude::*;
 use bevy_rapier2d::prelude::*;
 use serde::Deserialize;
+use std::time::Duration;
 
 const WALL_TILE: i32 = 1;
 const PIT_TILE: i32 = 2;
-const MAX_LEVEL: usize = 4;
+
+// identifier of the entity marking where the second player starts, in levels playable in versus
+const VERSUS_SPAWN: &str = "player_2";
+
+// pixels per second, below which an orb with no fuel is considered stuck
+const STRANDED_V: f32 = 1.0;
 
 #[derive(Deserialize, Debug)]
 struct CustomData {
@@ -41,34 +55,22 @@ impl CustomData {
 /// Blueprint bundle with extracted data and sprite
 #[derive(Bundle, LdtkEntity)]
 struct LdtkEntityBundle {
-    #[with(LdtkOrb::new)]
+    #[from_entity_instance]
     ldtk: LdtkOrb,
     #[sprite_sheet_bundle]
     sprite_bundle: SpriteSheetBundle,
 }
 
-///  Contains data from LDTK entities for blueprinting
+///  Contains data from LDTK entities for blueprinting, resolved against archetypes on init
 #[derive(Component)]
 struct LdtkOrb {
-    identifier: String,
-    mass: f32,
-    sfx_name: &'static str,
-    vfx_color: Vec4,
+    instance: EntityInstance,
 }
 
-impl LdtkOrb {
-    fn new(instance: &EntityInstance) -> LdtkOrb {
+impl From<&EntityInstance> for LdtkOrb {
+    fn from(instance: &EntityInstance) -> Self {
         LdtkOrb {
-            identifier: instance.identifier.clone(),
-            mass: instance.get_float_field("mass").cloned().unwrap_or(1.0),
-            sfx_name: match instance.identifier.as_str() {
-                "player" => "player-fall.ogg",
-                _ => "enemy-fall.ogg",
-            },
-            vfx_color: match instance.identifier.as_str() {
-                "player" => Vec4::new(0.2, 0.2, 1.0, 1.0),
-                _ => Vec4::new(1.0, 0.1, 0.1, 1.0),
-            },
+            instance: instance.clone(),
         }
     }
 }
@@ -102,12 +104,16 @@ struct Player;
 
 /// Marks npc, who can be defeated
 #[derive(Component)]
-struct Enemy;
+pub struct Enemy;
 
 /// Marks a UI element hidden except while in loading state
 #[derive(Component)]
 struct LoadingScreenElement;
 
+/// The LDtk project, loaded at startup so that it is ready by the time a level is chosen
+#[derive(Resource)]
+pub struct LevelProject(pub Handle<LdtkAsset>);
+
 /// Cache of all pit locations in the current level
 #[derive(Resource)]
 pub struct LevelPits(Vec<Vec2>);
@@ -134,10 +140,7 @@ impl Default for LevelPits {
 }
 
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn(LdtkWorldBundle {
-        ldtk_handle: asset_server.load("levels.ldtk"),
-        ..default()
-    });
+    commands.insert_resource(LevelProject(asset_server.load("levels.ldtk")));
 
     commands
         .spawn(TextBundle {
@@ -149,11 +152,61 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ..default()
                 },
             ),
+            visibility: Visibility::Hidden,
             ..default()
         })
         .insert(LoadingScreenElement);
 }
 
+// the world is spawned on demand, so that nothing is loaded behind the title screen, and not
+// before its orbs can be built
+fn spawn_world(
+    mut commands: Commands,
+    project: Res<LevelProject>,
+    worlds: Query<(), With<Handle<LdtkAsset>>>,
+) {
+    if worlds.is_empty() {
+        commands.spawn(LdtkWorldBundle {
+            ldtk_handle: project.0.clone(),
+            visibility: Visibility::Hidden,
+            ..default()
+        });
+    }
+}
+
+// a level asked for by iid is looked up by index, which is how replays and reports refer to it;
+// one that doesn't exist would never finish loading, however it was asked for
+fn check_level_selection(
+    mut selection: ResMut<LevelSelection>,
+    mut exits: EventWriter<AppExit>,
+    project: Res<LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+) {
+    let Some(project) = assets.get(&project.0) else {
+        return;
+    };
+
+    match &*selection {
+        LevelSelection::Index(index) => {
+            let count = project.iter_levels().count();
+            if *index >= count {
+                error!("no level {index}, there are only {count}");
+                exits.send(AppExit);
+            }
+        }
+        LevelSelection::Iid(iid) => {
+            match project.iter_levels().position(|level| level.iid == *iid) {
+                Some(index) => *selection = LevelSelection::Index(index),
+                None => {
+                    error!("no level with iid '{iid}'");
+                    exits.send(AppExit);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
 fn cache_pit_locs(
     mut cache: ResMut<LevelPits>,
     mut input: EventReader<CacheEvent>,
@@ -161,8 +214,7 @@ fn cache_pit_locs(
 ) {
     if input
         .iter()
-        .map(|event| matches!(event, CacheEvent::InvalidatePitCoords))
-        .fold(false, |acc, x| acc || x)
+        .any(|event| matches!(event, CacheEvent::InvalidatePitCoords))
     {
         cache.0.clear();
         for (_, transform) in tiles.iter().filter(|(tile, _)| matches!(tile, Tile::Pit)) {
@@ -172,15 +224,20 @@ fn cache_pit_locs(
 }
 
 fn detect_loaded(
+    mut tick: ResMut<Tick>,
+    mut attempt: ResMut<Attempt>,
     mut next_state: ResMut<NextState<AppState>>,
     mut level_events: EventReader<LevelEvent>,
     mut cache_events: EventWriter<CacheEvent>,
 ) {
     for level_event in level_events.iter() {
         match level_event {
-            LevelEvent::Spawned(_) => {
+            LevelEvent::Spawned(iid) => {
+                tick.0 = 0;
+                *attempt = Attempt::new(iid);
                 cache_events.send(CacheEvent::InvalidateColliderHierarchy);
                 cache_events.send(CacheEvent::InvalidatePitCoords);
+                cache_events.send(CacheEvent::InvalidateNavGrid);
             }
             LevelEvent::Transformed(iid) => {
                 info!("Loaded level {iid}");
@@ -191,12 +248,13 @@ fn detect_loaded(
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn enable_tiles(
     enable: bool,
 ) -> impl Fn(
     Query<&mut Visibility, With<LevelSet>>,
     Query<&mut Visibility, (With<LoadingScreenElement>, Without<LevelSet>)>,
-) -> () {
+) {
     move |mut levels, mut elements| {
         for mut level in levels.iter_mut() {
             *level.as_mut() = if enable {
@@ -298,7 +356,7 @@ fn init_cells(
                 batch.insert(Tile::Pit).with_children(|children| {
                     collision::spawn_pit(children, &entry);
                     for wall in &walls {
-                        collision::spawn_pit_wall(children, &wall);
+                        collision::spawn_pit_wall(children, wall);
                     }
                 });
             }
@@ -308,52 +366,129 @@ fn init_cells(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn init_orb(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    levels: Query<&Handle<LdtkLevel>>,
+    level_assets: Res<Assets<LdtkLevel>>,
+    defaults: Res<archetype::DefaultArchetypes>,
+    archetypes: Res<Assets<archetype::Archetypes>>,
+    personality_defaults: Res<personality::DefaultPersonalities>,
+    personalities: Res<Assets<personality::Personalities>>,
+    versus: Option<Res<versus::Versus>>,
     mut effects: ResMut<Assets<vfx::EffectAsset>>,
-    mut query: Query<(Entity, &LdtkOrb), Added<LdtkOrb>>,
+    mut query: Query<(Entity, &LdtkOrb, &mut TextureAtlasSprite), Added<LdtkOrb>>,
 ) {
-    for (id, ldtk) in query.iter_mut() {
+    // the world isn't spawned until archetypes and personalities have loaded
+    let Some(archetypes) = archetypes.get(&defaults.0) else {
+        return;
+    };
+    let Some(personalities) = personalities.get(&personality_defaults.0) else {
+        return;
+    };
+
+    // puzzle levels give the player a limited budget of thrust, in seconds
+    let fuel = levels
+        .get_single()
+        .ok()
+        .and_then(|handle| level_assets.get(handle))
+        .and_then(|level| {
+            let budget = *level.level.get_float_field("fuel").ok()?;
+            // a level with no fuel at all would be lost before it began
+            match Duration::try_from_secs_f32(budget) {
+                Ok(budget) if !budget.is_zero() => Some(movement::Fuel::new(budget)),
+                _ => {
+                    warn!("{} has invalid fuel {budget}", level.level.identifier);
+                    None
+                }
+            }
+        });
+
+    for (id, ldtk, mut sprite) in query.iter_mut() {
+        let archetype = archetypes.resolve(&ldtk.instance);
+
+        // with nobody to steer it, the second player sits out
+        if archetype.role == Role::Player && archetype.seat != Seat::One && versus.is_none() {
+            commands.entity(id).despawn_recursive();
+            continue;
+        }
+
         let mut batch = commands.entity(id);
 
+        // add appearance
+        let sprite_size =
+            Vec2::splat(archetype::ORB_SPRITE_SIZE * archetype.radius / archetype::ORB_RADIUS);
+        if let Some(path) = &archetype.sprite {
+            batch
+                .remove::<(TextureAtlasSprite, Handle<TextureAtlas>)>()
+                .insert(Sprite {
+                    custom_size: Some(sprite_size),
+                    ..default()
+                })
+                .insert(asset_server.load::<Image, _>(path));
+        } else if archetype.radius != archetype::ORB_RADIUS {
+            sprite.custom_size = Some(sprite_size);
+        }
+
         // add physics
         batch
             .insert(RigidBody::Dynamic)
             .insert(Velocity::default())
             .insert(ExternalImpulse::default())
-            .with_children(|children| collision::spawn_orb(children, ldtk.mass));
+            .insert(collision::Interpolated::default())
+            .with_children(|children| {
+                collision::spawn_orb(
+                    children,
+                    archetype.mass,
+                    archetype.radius,
+                    archetype.restitution,
+                )
+            });
 
         // add movement and fall fx
-        let effect_handle = vfx::allocate_thrust_sparks(&mut effects, ldtk.vfx_color);
-        batch.insert(Orb {
+        let effect_handle = vfx::allocate_thrust_sparks(&mut effects, archetype.spark_color);
+        let orb = Orb {
             vfx: effect_handle,
-            sfx: ldtk.sfx_name.into(),
-        });
+            sfx: archetype.fall_sfx.clone(),
+            radius: archetype.radius,
+        };
+        batch
+            .insert(archetype.handling())
+            .insert(rewind::Rewindable {
+                orb: orb.clone(),
+                mass: archetype.mass,
+                restitution: archetype.restitution,
+            })
+            .insert(orb);
+
+        if archetype.shove {
+            let effect_handle = vfx::allocate_thrust_sparks(&mut effects, vfx::SHOVE_COLOR);
+            batch.insert(movement::Shove::new(effect_handle));
+        }
 
         // add gameplay
-        match ldtk.identifier.as_str() {
-            "player" => {
-                batch.insert(Player).insert(PlayerInput);
-            }
-            "d_resignation" => {
-                batch.insert(Enemy);
-            }
-            "d_intransigence" => {
-                batch.insert(Enemy);
-                ai::spawn_intransigence(&mut batch);
+        match archetype.role {
+            Role::Player => {
+                batch.insert(Player).insert(PlayerInput(archetype.seat));
+                if let Some(fuel) = fuel {
+                    batch.insert(fuel);
+                }
             }
-            "d_cowardice" => {
+            Role::Enemy => {
                 batch.insert(Enemy);
-                ai::spawn_cowardice(&mut batch);
             }
-            "d_malice" => {
-                batch.insert(Enemy);
-                ai::spawn_malice(&mut batch);
-            }
-            _ => {
-                warn!("unknown LDTK entity '{}'", ldtk.identifier);
+            Role::Neutral => (),
+        }
+
+        if let Some(personality) = &archetype.personality {
+            if !ai::spawn_personality(&mut batch, personalities, personality) {
+                warn!(
+                    "{} has unknown personality '{personality}'",
+                    ldtk.instance.identifier
+                );
             }
-        };
+        }
     }
 }
 
@@ -381,55 +516,244 @@ fn init_txt(
     }
 }
 
+/// Display name for a level, from its optional `name` field or else its identifier
+pub fn level_name(level: &ldtk::Level) -> String {
+    level
+        .get_string_field("name")
+        .cloned()
+        .unwrap_or_else(|_| level.identifier.replace('_', " "))
+}
+
+/// Targets for a star rating, from the level's optional `par_time` and `par_thrusts` fields;
+/// each one left out is a star fewer to be had on that level
+pub struct Par {
+    /// seconds
+    pub time: Option<f32>,
+    pub thrusts: Option<u32>,
+}
+
+impl Par {
+    /// One for winning, plus one for each par set
+    pub fn max_stars(&self) -> u32 {
+        1 + self.time.is_some() as u32 + self.thrusts.is_some() as u32
+    }
+}
+
+pub fn par(level: &ldtk::Level) -> Par {
+    Par {
+        time: level.get_float_field("par_time").ok().copied(),
+        thrusts: level
+            .get_int_field("par_thrusts")
+            .ok()
+            .map(|thrusts| (*thrusts).max(0) as u32),
+    }
+}
+
+/// Indices of the levels that can be played in versus, having a spawn point for each player
+pub fn versus_levels(project: &LdtkAsset) -> Vec<usize> {
+    project
+        .iter_levels()
+        .enumerate()
+        .filter(|(_, level)| {
+            level
+                .layer_instances
+                .iter()
+                .flatten()
+                .flat_map(|layer| layer.entity_instances.iter())
+                .any(|entity| entity.identifier == VERSUS_SPAWN)
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// A world in the LDtk project, with its levels and their indices in play order
+pub struct Chapter<'a> {
+    pub name: Option<String>,
+    pub levels: Vec<(usize, &'a ldtk::Level)>,
+}
+
+/// Groups levels by world; a project without multiple worlds is a single unnamed chapter
+pub fn chapters(project: &LdtkAsset) -> Vec<Chapter<'_>> {
+    let top_level = (None, &project.project.levels);
+    let worlds = project
+        .project
+        .worlds
+        .iter()
+        .map(|world| (Some(world.identifier.replace('_', " ")), &world.levels));
+
+    // iter_levels() visits top-level levels before those in worlds, so indices follow suit
+    let mut index = 0;
+    let mut chapters = Vec::new();
+    for (name, levels) in std::iter::once(top_level).chain(worlds) {
+        if levels.is_empty() {
+            continue;
+        }
+
+        chapters.push(Chapter {
+            name,
+            levels: levels
+                .iter()
+                .enumerate()
+                .map(|(offset, level)| (index + offset, level))
+                .collect(),
+        });
+        index += levels.len();
+    }
+
+    chapters
+}
+
+/// The level that follows the given one, if any: named by its `next_level` field, or else the
+/// next in the project, carrying on into the next world at the end of each one
+pub fn next_level<'a>(project: &'a LdtkAsset, iid: &str) -> Option<(usize, &'a ldtk::Level)> {
+    let (index, level) = project
+        .iter_levels()
+        .enumerate()
+        .find(|(_, level)| level.iid == iid)?;
+
+    let in_order = || project.iter_levels().enumerate().nth(index + 1);
+
+    match level.get_maybe_string_field("next_level") {
+        Ok(Some(identifier)) if !identifier.is_empty() => {
+            project
+                .iter_levels()
+                .enumerate()
+                .find(|(_, level)| level.identifier == *identifier)
+                .or_else(|| {
+                    // a typo shouldn't end the game early
+                    warn!("{} has unknown next_level '{identifier}'", level.identifier);
+                    in_order()
+                })
+        }
+        _ => in_order(),
+    }
+}
+
+/// Moves on to the level after the given one, or to the ending if there isn't one
+pub fn advance(
+    commands: &mut Commands,
+    next_state: &mut NextState<AppState>,
+    project: Option<&LdtkAsset>,
+    iid: &str,
+) {
+    match project.and_then(|project| next_level(project, iid)) {
+        Some((index, _)) => {
+            commands.insert_resource(LevelSelection::Index(index));
+            next_state.set(AppState::Loading);
+        }
+        None => next_state.set(AppState::Finished),
+    }
+}
+
+/// Despawns the whole LDtk world; the next level chosen will spawn a fresh one
+pub fn unload(commands: &mut Commands, worlds: &Query<Entity, With<Handle<LdtkAsset>>>) {
+    for world in worlds.iter() {
+        commands.entity(world).despawn_recursive();
+    }
+}
+
+/// Reloads the current level from scratch
+pub fn restart(commands: &mut Commands, next_state: &mut NextState<AppState>, level: Entity) {
+    commands.entity(level).insert(Respawn);
+    next_state.set(AppState::Loading);
+}
+
+#[allow(clippy::type_complexity)]
 fn respawn_after_death(
     mut commands: Commands,
     mut next_state: ResMut<NextState<AppState>>,
+    mut outcomes: EventWriter<OutcomeEvent>,
     level: Query<Entity, With<Handle<LdtkLevel>>>,
-    players: Query<&Player>,
+    players: Query<&Player, Without<rewind::Fallen>>,
+    orbs: Query<(&Velocity, Option<&movement::Fuel>), With<Orb>>,
+    falling: Query<(), With<Falling>>,
 ) {
-    if players.is_empty() {
-        commands.entity(level.single()).insert(Respawn);
-        next_state.set(AppState::Loading);
+    // several ticks can run before the restart takes effect, and the level is only lost once
+    if next_state.0.is_some() {
+        return;
     }
+
+    // out of fuel and at rest, with nothing left in motion that could still win the level, e.g.
+    // an enemy still rolling towards a pit after a bank shot
+    let stranded = orbs.iter().any(|(_, fuel)| fuel.is_some())
+        && falling.is_empty()
+        && orbs.iter().all(|(velocity, fuel)| {
+            fuel.map_or(true, movement::Fuel::is_empty) && velocity.linvel.length() < STRANDED_V
+        });
+
+    if players.is_empty() || stranded {
+        outcomes.send(OutcomeEvent::Defeat);
+        restart(&mut commands, &mut next_state, level.single());
+    }
+}
+
+fn count_knockouts(mut attempt: ResMut<Attempt>, fallen: Query<(), (With<Enemy>, Added<Falling>)>) {
+    attempt.knocked += fallen.iter().count() as u32;
 }
 
+// the next level is loaded from the results screen
+#[allow(clippy::too_many_arguments)]
 fn advance_after_victory(
-    mut commands: Commands,
     mut next_state: ResMut<NextState<AppState>>,
-    level: Res<LevelSelection>,
-    enemies: Query<&Enemy>,
+    mut outcomes: EventWriter<OutcomeEvent>,
+    mut results: ResMut<results::Results>,
+    tick: Res<Tick>,
+    attempt: Res<Attempt>,
+    progress: Res<save::Progress>,
+    project: Res<LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+    enemies: Query<&Enemy, Without<rewind::Fallen>>,
 ) {
+    // likewise for the results screen, and the level is only won once
+    if next_state.0.is_some() {
+        return;
+    }
+
     if enemies.is_empty() {
-        if let LevelSelection::Index(i) = level.into_inner() {
-            let next_level = (i + 1) % MAX_LEVEL;
-            commands.insert_resource(LevelSelection::Index(next_level));
-            next_state.set(AppState::Loading);
-        }
+        outcomes.send(OutcomeEvent::Victory);
+
+        let level = assets
+            .get(&project.0)
+            .and_then(|project| project.get_level(&LevelSelection::Iid(attempt.level.clone())));
+        *results = results::Results::score(level, &tick, &attempt, progress.level(&attempt.level));
+
+        next_state.set(AppState::Results);
     }
 }
 
-pub fn plugin(level_select: usize) -> impl Plugin {
+pub fn plugin(level_select: LevelSelection) -> impl Plugin {
     OpaquePlugin(move |app| {
         app.add_plugins(LdtkPlugin)
             .add_systems(Startup, setup)
             .add_systems(
                 Update,
                 (
-                    (
-                        init_cells.pipe(super::handle),
-                        init_orb,
-                        init_txt,
-                        detect_loaded,
-                    )
-                        .run_if(in_state(AppState::Loading)),
+                    check_level_selection.before(spawn_world),
+                    spawn_world
+                        .run_if(archetype::archetypes_loaded)
+                        .run_if(personality::personalities_loaded),
+                    init_cells.pipe(super::handle),
+                    init_orb,
+                    init_txt,
+                    detect_loaded,
+                )
+                    .run_if(in_state(AppState::Loading)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    // versus decides its rounds by itself
                     (respawn_after_death, advance_after_victory)
-                        .run_if(in_state(AppState::Playing)),
-                ),
+                        .run_if(not(resource_exists::<versus::Versus>())),
+                    count_knockouts.before(advance_after_victory),
+                )
+                    .in_set(TickSet::Outcome)
+                    .run_if(in_state(AppState::Playing)),
             )
             .add_systems(PostUpdate, cache_pit_locs)
             .add_systems(OnEnter(AppState::Loading), enable_tiles(false))
             .add_systems(OnEnter(AppState::Playing), enable_tiles(true))
-            .insert_resource(LevelSelection::Index(level_select))
+            .insert_resource(level_select.clone())
             .init_resource::<LevelPits>()
             .register_default_ldtk_entity::<LdtkEntityBundle>()
             .register_ldtk_entity::<TipBundle>("txt");
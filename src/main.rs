@@ -1,43 +1,151 @@
 use bevy::{prelude::*, render::camera::ScalingMode};
+use bevy_ecs_ldtk::LevelSelection;
 use bevy_rapier2d::prelude::*;
 use bevy_tweening::{lens::TransformScaleLens, *};
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
 mod ai;
+mod archetype;
 mod collision;
+mod controls;
+mod ghost;
+mod headless;
+mod hud;
 mod level;
+mod menu;
 mod movement;
+mod nav;
+mod pause;
+mod personality;
+mod replay;
+mod results;
+mod rewind;
+mod save;
+mod title;
+mod versus;
 mod vfx;
 
-// pixels per second
-const MAX_V: f32 = 3000.0;
+// gameplay and physics advance in fixed steps, independent of frame rate
+const TIMESTEP: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+const FALL_DURATION: Duration = Duration::from_millis(1500);
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, States)]
 enum AppState {
     #[default]
+    Menu,
+    LevelSelect,
     Loading,
     Playing,
+    Paused,
+    Controls,
+    /// a level has been beaten, and how well is being shown
+    Results,
+    /// the last level has been beaten
+    Finished,
+    /// a versus match has been won, and by whom is being shown
+    MatchOver,
+}
+
+/// Stages of a simulation tick, in order
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TickSet {
+    /// player intent is gathered into InputEvents
+    Input,
+    /// orbs are pushed around, before physics
+    Movement,
+    /// collisions are acted on, after physics
+    Interaction,
+    /// victory or defeat is decided
+    Outcome,
+}
+
+/// Simulation ticks elapsed in the current level, counting the one in progress
+#[derive(Resource, Default)]
+struct Tick(u32);
+
+/// Running totals for the current attempt at a level
+#[derive(Resource, Default)]
+struct Attempt {
+    /// iid of the level being attempted
+    level: String,
+    /// separate bursts of acceleration, however long each one lasted
+    thrusts: u32,
+    thrusting: bool,
+    /// separate applications of the brakes, likewise
+    brakes: u32,
+    braking: bool,
+    /// enemies sent into pits
+    knocked: u32,
+}
+
+impl Attempt {
+    fn new(level: &str) -> Self {
+        Attempt {
+            level: level.to_owned(),
+            ..default()
+        }
+    }
+
+    fn count_inputs(&mut self, thrusting: bool, braking: bool) {
+        if thrusting && !self.thrusting {
+            self.thrusts += 1;
+        }
+        self.thrusting = thrusting;
+
+        if braking && !self.braking {
+            self.brakes += 1;
+        }
+        self.braking = braking;
+    }
 }
 
-/// Player button presses
+/// Player button presses, from whichever seat made them
 #[derive(Event)]
-enum InputEvent {
+struct InputEvent {
+    seat: controls::Seat,
+    intent: Intent,
+}
+
+impl InputEvent {
+    fn new(seat: controls::Seat, intent: Intent) -> Self {
+        InputEvent { seat, intent }
+    }
+}
+
+/// What a player is trying to do
+#[derive(Clone, Copy)]
+enum Intent {
     Decelerate,
+    /// direction to thrust in, with a length of up to 1 for partial thrust
     Accelerate(Vec2),
+    /// building up a shove, which goes off on the first tick without one
+    Charge,
+    /// stepping back through recent history, one tick per tick
+    Rewind,
 }
 
 /// Interactions detected by physics
 #[derive(Event)]
+#[allow(clippy::enum_variant_names)]
 enum InteractionEvent {
     OrbHitOrb,
     OrbHitWall,
     OrbHitPit(Entity),
 }
 
+/// Results of play, detected by level
+#[derive(Event, Clone, Copy, Debug)]
+enum OutcomeEvent {
+    Victory,
+    Defeat,
+}
+
 #[derive(Event)]
 enum CacheEvent {
     InvalidateColliderHierarchy,
     InvalidatePitCoords,
+    InvalidateNavGrid,
 }
 
 /// Has interactions on contact
@@ -48,15 +156,24 @@ enum Tile {
 }
 
 /// Moves around the level, interacting with other orbs and with tiles
-#[derive(Component)]
+#[derive(Component, Clone)]
 struct Orb {
     sfx: String,
     vfx: Handle<vfx::EffectAsset>,
+    radius: f32,
 }
 
-/// Can be moved with the keyboard
+/// Steered by the player in the given seat
 #[derive(Component, Default)]
-struct PlayerInput;
+struct PlayerInput(controls::Seat);
+
+/// Shrinking into a pit; out of play once the remaining time runs out
+#[derive(Component)]
+struct Falling(Duration);
+
+fn advance_tick(mut tick: ResMut<Tick>) {
+    tick.0 += 1;
+}
 
 fn setup(mut commands: Commands) {
     let bounds = Vec3::new(4096.0, 2304.0, 0.0);
@@ -75,162 +192,311 @@ fn setup(mut commands: Commands) {
     });
 }
 
-fn keyboard_input(input: Res<Input<KeyCode>>, mut events: EventWriter<InputEvent>) {
-    // braking takes priority
-    if input.pressed(KeyCode::Space) {
-        events.send(InputEvent::Decelerate);
-        return;
-    }
+#[allow(clippy::type_complexity)]
+fn move_player(
+    time: Res<FixedTime>,
+    mut attempt: ResMut<Attempt>,
+    mut events: EventReader<InputEvent>,
+    mut query: Query<
+        (
+            &PlayerInput,
+            &mut Transform,
+            &mut Velocity,
+            &mut ExternalImpulse,
+            &movement::Handling,
+            Option<&mut movement::Fuel>,
+        ),
+        With<Orb>,
+    >,
+) {
+    let dt = time.period.as_secs_f32();
 
-    // if not braking, we may thrust
-    let mut thrust = Vec2::ZERO;
+    // several devices may be in use at once, so combine their input; braking takes priority
+    let mut decelerate = [false; controls::Seat::COUNT];
+    let mut thrust = [Vec2::ZERO; controls::Seat::COUNT];
 
-    if input.pressed(KeyCode::Right) || input.pressed(KeyCode::D) {
-        thrust.x += 1.0;
+    for event in events.iter() {
+        let seat = event.seat.index();
+        match event.intent {
+            Intent::Decelerate => decelerate[seat] = true,
+            Intent::Accelerate(vector) => thrust[seat] += vector,
+            Intent::Charge | Intent::Rewind => (),
+        }
     }
 
-    if input.pressed(KeyCode::Left) || input.pressed(KeyCode::A) {
-        thrust.x -= 1.0;
-    }
+    // only the first seat's inputs count towards a score
+    let first = controls::Seat::One.index();
+    attempt.count_inputs(
+        !decelerate[first] && thrust[first] != Vec2::ZERO,
+        decelerate[first],
+    );
 
-    if input.pressed(KeyCode::Up) || input.pressed(KeyCode::W) {
-        thrust.y += 1.0;
-    }
+    for (input, mut transform, mut velocity, mut impulse, handling, fuel) in query.iter_mut() {
+        let seat = input.0.index();
+        if !decelerate[seat] && thrust[seat] == Vec2::ZERO {
+            continue;
+        }
 
-    if input.pressed(KeyCode::Down) || input.pressed(KeyCode::S) {
-        thrust.y -= 1.0;
-    }
+        // on levels with a fuel budget, an empty tank refuses both thrust and brakes
+        if fuel.is_some_and(|mut fuel| !fuel.burn(time.period)) {
+            continue;
+        }
 
-    if thrust != Vec2::ZERO {
-        thrust = thrust.normalize();
-        events.send(InputEvent::Accelerate(thrust));
+        if decelerate[seat] {
+            movement::decelerate_orb(dt, handling, velocity.as_mut(), impulse.as_mut())
+        } else {
+            movement::accelerate_orb(
+                dt,
+                handling,
+                thrust[seat].clamp_length_max(1.0),
+                transform.as_mut(),
+                velocity.as_mut(),
+                impulse.as_mut(),
+            );
+        }
     }
 }
 
-fn move_player(
-    time: Res<Time>,
+#[allow(clippy::type_complexity)]
+fn shove_player(
+    time: Res<FixedTime>,
     mut events: EventReader<InputEvent>,
     mut query: Query<
-        (&mut Transform, &mut Velocity, &mut ExternalImpulse),
-        (With<PlayerInput>, With<Orb>),
+        (
+            &PlayerInput,
+            &Transform,
+            &mut ExternalImpulse,
+            &mut movement::Shove,
+        ),
+        With<Orb>,
     >,
 ) {
+    let mut charging = [false; controls::Seat::COUNT];
     for event in events.iter() {
-        match *event {
-            InputEvent::Decelerate => {
-                for (_, mut velocity, mut impulse) in query.iter_mut() {
-                    movement::decelerate_orb(&time, velocity.as_mut(), impulse.as_mut())
-                }
-            }
-            InputEvent::Accelerate(thrust) => {
-                for (mut transform, mut velocity, mut impulse) in query.iter_mut() {
-                    movement::accelerate_orb(
-                        &time,
-                        thrust,
-                        transform.as_mut(),
-                        velocity.as_mut(),
-                        impulse.as_mut(),
-                    );
-                }
-            }
+        if let Intent::Charge = event.intent {
+            charging[event.seat.index()] = true;
         }
     }
+
+    for (input, transform, mut impulse, mut shove) in query.iter_mut() {
+        if charging[input.0.index()] {
+            movement::charge_shove(time.period, shove.as_mut());
+        } else {
+            movement::release_shove(transform, shove.as_mut(), impulse.as_mut());
+        }
+    }
+}
+
+fn cool_shoves(time: Res<FixedTime>, mut query: Query<&mut movement::Shove>) {
+    for mut shove in query.iter_mut() {
+        movement::cool_shove(time.period, shove.as_mut());
+    }
 }
 
-fn cap_velocity(mut query: Query<&mut Velocity, With<Orb>>) {
-    for mut velocity in query.iter_mut() {
-        velocity.linvel = velocity.linvel.clamp_length_max(MAX_V);
+fn cap_velocity(mut query: Query<(&mut Velocity, &movement::Handling), With<Orb>>) {
+    for (mut velocity, handling) in query.iter_mut() {
+        velocity.linvel = velocity.linvel.clamp_length_max(handling.max_speed);
     }
 }
 
-fn trigger_vfx(mut commands: Commands, mut query: Query<(Entity, &Orb, &ExternalImpulse)>) {
-    for (entity, orb, impulse) in query.iter_mut() {
+fn trigger_vfx(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Orb, &ExternalImpulse, Option<&movement::Shove>)>,
+) {
+    for (entity, orb, impulse, shove) in query.iter_mut() {
         if impulse.impulse != Vec2::ZERO {
+            let effect = match shove {
+                Some(shove) if shove.released => shove.vfx.clone(),
+                _ => orb.vfx.clone(),
+            };
+
             commands.entity(entity).with_children(|children| {
-                vfx::instantiate_thrust_sparks(children, orb.vfx.clone(), impulse.impulse);
+                vfx::instantiate_thrust_sparks(children, effect, impulse.impulse);
             });
         }
     }
 }
 
-fn trigger_interaction(
+fn play_interaction_sfx(
     assets: Res<AssetServer>,
     mut commands: Commands,
     mut events: EventReader<InteractionEvent>,
     orbs: Query<&Orb>,
 ) {
     for event in events.iter() {
-        match event {
-            InteractionEvent::OrbHitWall => {
-                commands.spawn(AudioBundle {
-                    source: assets.load("pobble.ogg"),
-                    ..default()
-                });
-            }
-            InteractionEvent::OrbHitOrb => {
-                commands.spawn(AudioBundle {
-                    source: assets.load("pobblebonk.ogg"),
-                    ..default()
-                });
-            }
-            InteractionEvent::OrbHitPit(entity) => {
-                if let Ok(orb) = orbs.get(*entity) {
-                    commands.spawn(AudioBundle {
-                        source: assets.load(&orb.sfx),
-                        ..default()
-                    });
-                }
+        let sfx = match event {
+            InteractionEvent::OrbHitWall => "pobble.ogg",
+            InteractionEvent::OrbHitOrb => "pobblebonk.ogg",
+            InteractionEvent::OrbHitPit(entity) => match orbs.get(*entity) {
+                Ok(orb) => orb.sfx.as_str(),
+                Err(_) => continue,
+            },
+        };
 
-                // shrink into oblivion
-                let tween = Tween::new(
-                    EaseFunction::QuadraticIn,
-                    Duration::from_millis(1500),
-                    TransformScaleLens {
-                        start: Vec3::ONE,
-                        end: Vec3::ZERO,
-                    },
-                )
-                .with_completed_event(0);
-
-                commands
-                    .entity(*entity)
-                    .remove::<Orb>()
-                    .insert(Animator::new(tween))
-                    .despawn_descendants()
-                    .with_children(collision::spawn_falling_orb);
-            }
+        commands.spawn(AudioBundle {
+            source: assets.load(sfx),
+            ..default()
+        });
+    }
+}
+
+fn trigger_interaction(
+    mut commands: Commands,
+    mut events: EventReader<InteractionEvent>,
+    orbs: Query<&Orb>,
+) {
+    for event in events.iter() {
+        if let InteractionEvent::OrbHitPit(entity) = event {
+            let Ok(&Orb { radius, .. }) = orbs.get(*entity) else {
+                continue;
+            };
+
+            // shrink into oblivion
+            let tween = Tween::new(
+                EaseFunction::QuadraticIn,
+                FALL_DURATION,
+                TransformScaleLens {
+                    start: Vec3::ONE,
+                    end: Vec3::ZERO,
+                },
+            );
+
+            commands
+                .entity(*entity)
+                .remove::<Orb>()
+                .insert(Falling(FALL_DURATION))
+                .insert(Animator::new(tween))
+                .despawn_descendants()
+                .with_children(|children| collision::spawn_falling_orb(children, radius));
         }
     }
 }
 
+// tweens run on frame time, so they must be held still along with the simulation
+fn enable_tweens(enable: bool) -> impl Fn(Query<&mut Animator<Transform>>) {
+    move |mut animators| {
+        for mut animator in animators.iter_mut() {
+            animator.state = if enable {
+                AnimatorState::Playing
+            } else {
+                AnimatorState::Paused
+            };
+        }
+    }
+}
+
+// counted in ticks rather than by the tween, so that outcomes are decided deterministically
 fn die_after_fall(
+    time: Res<FixedTime>,
+    tick: Res<Tick>,
     mut commands: Commands,
-    mut tween_events: EventReader<TweenCompleted>,
     mut cache_events: EventWriter<CacheEvent>,
+    mut query: Query<(Entity, &mut Falling)>,
 ) {
-    for fallen in tween_events.iter() {
-        commands.entity(fallen.entity).despawn_recursive();
-        cache_events.send(CacheEvent::InvalidateColliderHierarchy);
+    for (entity, mut falling) in query.iter_mut() {
+        if falling.0 > Duration::ZERO {
+            let diff = time.period.clamp(Duration::ZERO, falling.0);
+            falling.0 -= diff;
+        } else {
+            // hidden rather than despawned, so that a rewind can still bring it back
+            commands
+                .entity(entity)
+                .remove::<Falling>()
+                .insert(rewind::Fallen(tick.0))
+                .insert(Visibility::Hidden)
+                .insert(RigidBodyDisabled)
+                .despawn_descendants();
+            cache_events.send(CacheEvent::InvalidateColliderHierarchy);
+        }
     }
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let mut level_select = 0;
-    if let Some(arg1) = args.get(1) {
-        if let Ok(index) = arg1.parse() {
-            level_select = index;
+/// Command-line options
+struct Args {
+    headless: bool,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    /// by index in play order, or by iid
+    level: Option<LevelSelection>,
+}
+
+impl Args {
+    fn parse() -> Args {
+        let mut args = Args {
+            headless: false,
+            record: None,
+            replay: None,
+            level: None,
+        };
+
+        let mut argv = std::env::args().skip(1);
+        while let Some(arg) = argv.next() {
+            match arg.as_str() {
+                "--headless" => args.headless = true,
+                "--record" => args.record = argv.next().map(PathBuf::from),
+                "--replay" => args.replay = argv.next().map(PathBuf::from),
+                "--level" => {
+                    args.level = argv.next().map(|level| match level.parse() {
+                        Ok(index) => LevelSelection::Index(index),
+                        Err(_) => LevelSelection::Iid(level),
+                    })
+                }
+                _ => eprintln!("ignoring unknown argument '{arg}'"),
+            }
         }
+
+        args
     }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let playback = match args.replay.as_deref().map(replay::Replay::load) {
+        Some(Ok(replay)) => Some(replay),
+        Some(Err(cause)) => {
+            eprintln!("{cause:#}");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    // a replay can only be played back on the level it was recorded in; otherwise levels are
+    // chosen from the title screen, unless one was asked for
+    let level = match (&playback, args.level.clone()) {
+        (Some(replay), level) => {
+            if level.is_some() {
+                eprintln!("ignoring --level in favour of the replay's level");
+            }
+            LevelSelection::Index(replay.level)
+        }
+        (None, Some(level)) => level,
+        (None, None) => LevelSelection::Index(0),
+    };
 
-    App::new()
-        .add_plugins((
+    // unattended runs have nobody to navigate the title screen
+    let unattended = args.headless || playback.is_some();
+    let skip_title = unattended || args.level.is_some();
+
+    let mut app = App::new();
+
+    if args.headless {
+        app.add_plugins((headless::plugins(), headless::plugin()));
+    } else {
+        app.add_plugins(
             DefaultPlugins
                 .set(bevy::log::LogPlugin {
                     filter: "wgpu=error,naga=warn,shoveit=info".to_string(),
                     ..default()
                 })
                 .set(ImagePlugin::default_nearest())
+                // so that personalities can be tuned while the game runs
+                .set(AssetPlugin {
+                    watch_for_changes: bevy::asset::ChangeWatcher::with_delay(
+                        Duration::from_millis(200),
+                    ),
+                    ..default()
+                })
                 .set(WindowPlugin {
                     primary_window: Some(Window {
                         title: "Shove it!".into(),
@@ -238,30 +504,89 @@ fn main() {
                     }),
                     ..default()
                 }),
-            TweeningPlugin,
-            ai::plugin(),
-            level::plugin(level_select),
-            collision::plugin(),
-            vfx::plugin(),
-        ))
-        .add_state::<AppState>()
-        .add_event::<InputEvent>()
-        .add_event::<InteractionEvent>()
-        .add_event::<CacheEvent>()
-        .add_systems(Startup, setup)
-        .add_systems(
-            Update,
+        );
+    }
+
+    // plugin tuples top out at 15, so simulation and screens are added separately
+    app.add_plugins((
+        TweeningPlugin,
+        ai::plugin(),
+        archetype::plugin(),
+        personality::plugin(),
+        level::plugin(level),
+        collision::plugin(),
+        nav::plugin(),
+        controls::plugin(),
+        ghost::plugin(!unattended),
+        replay::plugin(args.record.clone(), playback),
+        rewind::plugin(),
+        save::plugin(!unattended),
+        vfx::plugin(args.headless),
+    ))
+    .add_plugins((
+        hud::plugin(),
+        menu::plugin(),
+        pause::plugin(),
+        results::plugin(),
+        title::plugin(),
+        versus::plugin(),
+    ))
+    .add_state::<AppState>()
+    .add_event::<InputEvent>()
+    .add_event::<InteractionEvent>()
+    .add_event::<OutcomeEvent>()
+    .add_event::<CacheEvent>()
+    .insert_resource(FixedTime::new(TIMESTEP))
+    .init_resource::<Tick>()
+    .init_resource::<Attempt>()
+    .configure_sets(
+        FixedUpdate,
+        (TickSet::Input, TickSet::Movement)
+            .chain()
+            .before(PhysicsSet::SyncBackend),
+    )
+    .configure_sets(
+        FixedUpdate,
+        (TickSet::Interaction, TickSet::Outcome)
+            .chain()
+            .after(PhysicsSet::Writeback),
+    )
+    .add_systems(Startup, setup)
+    .add_systems(OnEnter(AppState::Playing), enable_tweens(true))
+    .add_systems(OnExit(AppState::Playing), enable_tweens(false))
+    .add_systems(
+        FixedUpdate,
+        (
+            advance_tick.before(TickSet::Input),
+            cool_shoves.after(advance_tick).before(TickSet::Movement),
             (
-                keyboard_input.before(move_player),
                 move_player.before(cap_velocity),
+                shove_player.after(move_player),
                 cap_velocity,
-                trigger_vfx.after(move_player),
+                trigger_vfx.after(shove_player),
+            )
+                .in_set(TickSet::Movement),
+            (
+                play_interaction_sfx
+                    .run_if(resource_exists::<Assets<AudioSource>>())
+                    .before(trigger_interaction),
                 trigger_interaction,
                 die_after_fall,
             )
-                .run_if(in_state(AppState::Playing)),
+                .in_set(TickSet::Interaction),
         )
-        .run();
+            .run_if(in_state(AppState::Playing)),
+    );
+
+    if skip_title {
+        app.insert_resource(State::new(AppState::Loading));
+    }
+
+    app.run();
+
+    if args.headless {
+        std::process::exit(headless::exit_code());
+    }
 }
 
 struct OpaquePlugin<T>(T)
@@ -0,0 +1,229 @@
+use bevy::prelude::*;
+
+use crate::{controls, OpaquePlugin};
+
+const COLOR_IDLE: Color = Color::rgb(0.6, 0.6, 0.6);
+const COLOR_SELECTED: Color = Color::WHITE;
+
+/// Everything a menu item can do, handled by whichever screen spawned the menu
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuAction {
+    /// shown but not selectable, e.g. a locked level
+    Disabled,
+    Back,
+    Continue,
+    LevelSelect,
+    StartLevel(usize),
+    Versus,
+    Settings,
+    Quit,
+    Resume,
+    Restart,
+    NextLevel,
+    QuitToMenu,
+    Rebind(controls::Seat, controls::Action),
+    ResetControls,
+}
+
+/// Sent when a menu item is activated, or the menu is dismissed
+#[derive(Event)]
+pub struct MenuEvent(pub MenuAction);
+
+/// While present, menus ignore input so that a screen can capture it instead
+#[derive(Resource)]
+pub struct MenuLocked;
+
+/// Root of a full-screen list of items, navigable by keyboard, gamepad or mouse
+#[derive(Component)]
+pub struct Menu {
+    selected: usize,
+    len: usize,
+}
+
+impl Menu {
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+}
+
+#[derive(Component)]
+struct MenuItem {
+    index: usize,
+    action: MenuAction,
+}
+
+pub fn spawn_menu(
+    commands: &mut Commands,
+    title: &str,
+    items: Vec<(String, MenuAction)>,
+    selected: usize,
+) -> Entity {
+    let len = items.len();
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(16.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.75).into(),
+            z_index: ZIndex::Global(10),
+            ..default()
+        })
+        .insert(Menu {
+            selected: selected.min(len.saturating_sub(1)),
+            len,
+        })
+        .with_children(|root| {
+            root.spawn(
+                TextBundle::from_section(
+                    title,
+                    TextStyle {
+                        color: Color::WHITE,
+                        font_size: 96.0,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::bottom(Val::Px(32.0)),
+                    ..default()
+                }),
+            );
+
+            for (index, (label, action)) in items.into_iter().enumerate() {
+                root.spawn(ButtonBundle {
+                    background_color: Color::NONE.into(),
+                    ..default()
+                })
+                .insert(MenuItem { index, action })
+                .with_children(|item| {
+                    item.spawn(TextBundle::from_section(
+                        label,
+                        TextStyle {
+                            color: COLOR_IDLE,
+                            font_size: 64.0,
+                            ..default()
+                        },
+                    ));
+                });
+            }
+        })
+        .id()
+}
+
+pub fn despawn_menus<T: Component>(
+    mut commands: Commands,
+    menus: Query<Entity, (With<Menu>, With<T>)>,
+) {
+    for menu in menus.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
+
+// navigation is fixed, so that no binding can lock the player out of the menus
+fn navigate_menu(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    mut menus: Query<&mut Menu>,
+    items: Query<(&MenuItem, &Parent)>,
+    mut events: EventWriter<MenuEvent>,
+) {
+    let pressed = |key_codes: &[KeyCode], button_type: GamepadButtonType| {
+        keys.any_just_pressed(key_codes.iter().copied())
+            || gamepads
+                .iter()
+                .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+
+    let up = pressed(&[KeyCode::Up, KeyCode::W], GamepadButtonType::DPadUp);
+    let down = pressed(&[KeyCode::Down, KeyCode::S], GamepadButtonType::DPadDown);
+    let confirm = pressed(&[KeyCode::Return, KeyCode::Space], GamepadButtonType::South);
+    let back = pressed(&[KeyCode::Escape], GamepadButtonType::East);
+
+    for mut menu in menus.iter_mut() {
+        if menu.len == 0 {
+            continue;
+        }
+
+        if up {
+            menu.selected = (menu.selected + menu.len - 1) % menu.len;
+        } else if down {
+            menu.selected = (menu.selected + 1) % menu.len;
+        }
+    }
+
+    if back {
+        events.send(MenuEvent(MenuAction::Back));
+    } else if confirm {
+        for (item, parent) in items.iter() {
+            if let Ok(menu) = menus.get(parent.get()) {
+                if menu.selected == item.index {
+                    events.send(MenuEvent(item.action));
+                }
+            }
+        }
+    }
+}
+
+fn point_at_menu(
+    mut menus: Query<&mut Menu>,
+    items: Query<(&MenuItem, &Parent, &Interaction), Changed<Interaction>>,
+    mut events: EventWriter<MenuEvent>,
+) {
+    for (item, parent, interaction) in items.iter() {
+        if let Ok(mut menu) = menus.get_mut(parent.get()) {
+            match interaction {
+                Interaction::Hovered => menu.selected = item.index,
+                Interaction::Pressed => {
+                    menu.selected = item.index;
+                    events.send(MenuEvent(item.action));
+                }
+                Interaction::None => (),
+            }
+        }
+    }
+}
+
+fn highlight_selection(
+    menus: Query<&Menu>,
+    items: Query<(&MenuItem, &Parent, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (item, parent, children) in items.iter() {
+        if let Ok(menu) = menus.get(parent.get()) {
+            let color = if menu.selected == item.index {
+                COLOR_SELECTED
+            } else {
+                COLOR_IDLE
+            };
+
+            for child in children.iter() {
+                if let Ok(mut text) = texts.get_mut(*child) {
+                    for section in text.sections.iter_mut() {
+                        section.style.color = color;
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.add_event::<MenuEvent>().add_systems(
+            Update,
+            (
+                (navigate_menu, point_at_menu).run_if(not(resource_exists::<MenuLocked>())),
+                highlight_selection,
+            )
+                .chain(),
+        );
+    })
+}
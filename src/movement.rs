@@ -1,54 +1,233 @@
 use bevy::{math::Vec3Swizzles, prelude::*};
 use bevy_rapier2d::prelude::*;
-use std::f32::consts::PI;
+use std::{f32::consts::PI, time::Duration};
+
+use crate::vfx::EffectAsset;
 
 // pixels per second per second
-const ACCEL_V: f32 = 750.0;
-const DECEL_V: f32 = -1500.0;
+pub const ACCEL_V: f32 = 750.0;
+pub const DECEL_V: f32 = -1500.0;
+
+// pixels per second
+pub const MAX_V: f32 = 3000.0;
+
+// radians per second
+pub const TURN_RATE: f32 = 4.0 * PI;
+
+/// How an orb moves under its own power; heavier or upgraded orbs can differ
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Handling {
+    pub accel: f32,
+    pub decel: f32,
+    pub turn_rate: f32,
+    pub max_speed: f32,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            accel: ACCEL_V,
+            decel: DECEL_V,
+            turn_rate: TURN_RATE,
+            max_speed: MAX_V,
+        }
+    }
+}
+
+impl Handling {
+    /// How much further than it would have drifted an orb can get by thrusting flat out
+    pub fn reach(&self, seconds: f32) -> f32 {
+        let to_top_speed = self.max_speed / self.accel;
+        if seconds <= to_top_speed {
+            0.5 * self.accel * seconds * seconds
+        } else {
+            0.5 * self.max_speed * to_top_speed + self.max_speed * (seconds - to_top_speed)
+        }
+    }
+}
 
 /// returns true if thrust was applied (otherwise, we are still turning)
 pub fn accelerate_orb(
-    time: &Res<Time>,
-    thrust: Vec2, // desired vector, normalised
+    dt: f32,
+    handling: &Handling,
+    thrust: Vec2, // desired vector, length 0-1 scales the thrust
     transform: &mut Transform,
     velocity: &mut Velocity,
     impulse: &mut ExternalImpulse,
 ) -> bool {
+    if !turn_orb(dt, handling, thrust, transform, velocity) {
+        return false;
+    }
+
+    // otherwise, apply thrust in the direction we are now facing
+    impulse.impulse = thrust * handling.accel * dt;
+    true
+}
+
+/// returns true if facing the goal by the end of this tick
+pub fn turn_orb(
+    dt: f32,
+    handling: &Handling,
+    goal: Vec2,
+    transform: &mut Transform,
+    velocity: &mut Velocity,
+) -> bool {
+    let goal = goal.normalize_or_zero();
     let forward = (transform.rotation * Vec3::Y).xy();
-    let forward_dot_goal = forward.dot(thrust);
+    let forward_dot_goal = forward.dot(goal);
 
-    // if facing ⋅ thrust is significant, attempt to rotate towards thrust
+    // if facing ⋅ goal is significant, attempt to rotate towards goal
     if (forward_dot_goal - 1.0).abs() > f32::EPSILON {
         // cancel any tumbling
         velocity.angvel = 0.0;
 
         // +ve=anticlockwise, -ve=clockwise (right hand rule)
         let right = (transform.rotation * Vec3::X).xy();
-        let right_dot_goal = right.dot(thrust);
+        let right_dot_goal = right.dot(goal);
         let sign = -f32::copysign(1.0, right_dot_goal);
 
         // avoid overshoot
         let max_angle = forward_dot_goal.clamp(-1.0, 1.0).acos();
         if max_angle > f32::EPSILON {
-            let rotation_angle = (sign * 4.0 * PI * time.delta_seconds()).min(max_angle);
-            transform.rotate_z(rotation_angle);
-            return false;
+            let turn_angle = handling.turn_rate * dt;
+            transform.rotate_z(sign * turn_angle.min(max_angle));
+
+            // small corrections, like those from an analog stick, needn't cost a tick of thrust
+            if turn_angle < max_angle {
+                return false;
+            }
         }
     }
 
-    // otherwise, apply thrust in the direction we are now facing
-    impulse.impulse = thrust * ACCEL_V * time.delta_seconds();
     true
 }
 
-pub fn decelerate_orb(time: &Res<Time>, velocity: &mut Velocity, impulse: &mut ExternalImpulse) {
+pub fn decelerate_orb(
+    dt: f32,
+    handling: &Handling,
+    velocity: &mut Velocity,
+    impulse: &mut ExternalImpulse,
+) {
     velocity.angvel = 0.0; // cheap, but w/e
 
     let mut antithrust = velocity.linvel.normalize();
-    antithrust = antithrust * DECEL_V * time.delta_seconds();
+    antithrust = antithrust * handling.decel * dt;
     antithrust = antithrust.clamp_length(0.0, velocity.linvel.length());
 
     if !antithrust.is_nan() {
         impulse.impulse = antithrust;
     }
 }
+
+// pixels per second, at full charge
+const SHOVE_V: f32 = 2000.0;
+const SHOVE_CHARGE: Duration = Duration::from_millis(750);
+const SHOVE_COOLDOWN: Duration = Duration::from_millis(2000);
+
+/// A burst of speed along the facing direction, built up while held and let go all at once
+#[derive(Component, Clone, Debug)]
+pub struct Shove {
+    pub charge: Duration,
+    pub cooldown: Duration,
+    /// set for the tick on which the shove went off
+    pub released: bool,
+    pub vfx: Handle<EffectAsset>,
+}
+
+impl Shove {
+    pub fn new(vfx: Handle<EffectAsset>) -> Self {
+        Shove {
+            charge: Duration::ZERO,
+            cooldown: Duration::ZERO,
+            released: false,
+            vfx,
+        }
+    }
+
+    pub fn ready(&self) -> bool {
+        self.cooldown.is_zero()
+    }
+
+    /// 0-1 proportion of a full charge
+    pub fn charge_level(&self) -> f32 {
+        self.charge.as_secs_f32() / SHOVE_CHARGE.as_secs_f32()
+    }
+
+    /// 0-1 proportion of the cooldown still to go
+    pub fn cooldown_level(&self) -> f32 {
+        self.cooldown.as_secs_f32() / SHOVE_COOLDOWN.as_secs_f32()
+    }
+}
+
+pub fn cool_shove(dt: Duration, shove: &mut Shove) {
+    shove.cooldown = shove.cooldown.saturating_sub(dt);
+    shove.released = false;
+}
+
+/// returns true if charging (otherwise, the shove is cooling down)
+pub fn charge_shove(dt: Duration, shove: &mut Shove) -> bool {
+    if !shove.ready() {
+        return false;
+    }
+
+    shove.charge = (shove.charge + dt).min(SHOVE_CHARGE);
+    true
+}
+
+/// returns true if a charged shove went off
+pub fn release_shove(
+    transform: &Transform,
+    shove: &mut Shove,
+    impulse: &mut ExternalImpulse,
+) -> bool {
+    if shove.charge.is_zero() {
+        return false;
+    }
+
+    let forward = (transform.rotation * Vec3::Y).xy();
+    impulse.impulse += forward * SHOVE_V * shove.charge_level();
+
+    shove.charge = Duration::ZERO;
+    shove.cooldown = SHOVE_COOLDOWN;
+    shove.released = true;
+    true
+}
+
+/// Limited thrust for puzzle levels, counted in seconds of thrusting or braking
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Fuel {
+    pub remaining: Duration,
+    pub budget: Duration,
+}
+
+impl Fuel {
+    pub fn new(budget: Duration) -> Self {
+        Fuel {
+            remaining: budget,
+            budget,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining.is_zero()
+    }
+
+    /// 0-1 proportion of the budget left
+    pub fn level(&self) -> f32 {
+        if self.budget.is_zero() {
+            return 0.0;
+        }
+
+        self.remaining.as_secs_f32() / self.budget.as_secs_f32()
+    }
+
+    /// returns false, consuming nothing, if there was none left
+    pub fn burn(&mut self, dt: Duration) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        self.remaining = self.remaining.saturating_sub(dt);
+        true
+    }
+}
@@ -0,0 +1,370 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_ecs_ldtk::prelude::*;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+};
+
+use crate::{CacheEvent, OpaquePlugin, Tile};
+
+const TILE_SIZE: f32 = 256.0;
+
+// extra cost, in tiles, of passing next to a pit, so that paths give them a wide berth
+const PIT_EDGE_COST: f32 = 2.0;
+
+const STEPS: [IVec2; 8] = [
+    IVec2::new(1, 0),
+    IVec2::new(-1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+/// Which tiles of the current level can be crossed, for finding a way around walls and pits
+#[derive(Resource, Default)]
+pub struct NavGrid {
+    blocked: HashSet<IVec2>,
+    pit_edges: HashSet<IVec2>,
+    min: IVec2,
+    max: IVec2,
+}
+
+/// A tile on the A* frontier, ordered so that the heap pops the most promising first
+struct Frontier {
+    cell: IVec2,
+    cost: f32,
+    estimate: f32,
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+fn cell(position: Vec2) -> IVec2 {
+    (position / TILE_SIZE).floor().as_ivec2()
+}
+
+fn centre(cell: IVec2) -> Vec2 {
+    (cell.as_vec2() + 0.5) * TILE_SIZE
+}
+
+// octile distance, which never overestimates with diagonal steps allowed
+fn heuristic(from: IVec2, to: IVec2) -> f32 {
+    let delta = (to - from).abs();
+    let (long, short) = (delta.max_element() as f32, delta.min_element() as f32);
+    long + (std::f32::consts::SQRT_2 - 1.0) * short
+}
+
+impl NavGrid {
+    fn from_tiles<'a>(tiles: impl IntoIterator<Item = (IVec2, Option<&'a Tile>)>) -> Self {
+        let mut grid = NavGrid::default();
+        let mut pits = Vec::new();
+        let mut bounds: Option<(IVec2, IVec2)> = None;
+
+        for (cell, tile) in tiles {
+            bounds = Some(bounds.map_or((cell, cell), |(min, max)| (min.min(cell), max.max(cell))));
+
+            match tile {
+                Some(Tile::Wall) => {
+                    grid.blocked.insert(cell);
+                }
+                Some(Tile::Pit) => {
+                    grid.blocked.insert(cell);
+                    pits.push(cell);
+                }
+                None => (),
+            }
+        }
+
+        if let Some((min, max)) = bounds {
+            grid.min = min;
+            grid.max = max;
+        }
+
+        for pit in pits {
+            for step in STEPS {
+                grid.pit_edges.insert(pit + step);
+            }
+        }
+
+        grid
+    }
+
+    fn passable(&self, cell: IVec2) -> bool {
+        cell.cmpge(self.min).all() && cell.cmple(self.max).all() && !self.blocked.contains(&cell)
+    }
+
+    // diagonal steps can't cut the corner of a wall or pit, which an orb is too wide to squeeze by
+    fn neighbours(&self, cell: IVec2) -> impl Iterator<Item = (IVec2, f32)> + '_ {
+        STEPS.iter().filter_map(move |step| {
+            let next = cell + *step;
+            let diagonal = step.x != 0 && step.y != 0;
+
+            let squeezed = diagonal
+                && !(self.passable(cell + IVec2::new(step.x, 0))
+                    && self.passable(cell + IVec2::new(0, step.y)));
+
+            if !self.passable(next) || squeezed {
+                return None;
+            }
+
+            let distance = if diagonal {
+                std::f32::consts::SQRT_2
+            } else {
+                1.0
+            };
+            let hazard = if self.pit_edges.contains(&next) {
+                PIT_EDGE_COST
+            } else {
+                0.0
+            };
+
+            Some((next, distance + hazard))
+        })
+    }
+
+    /// Waypoints from one position to another, by way of tile centres and ending at the goal;
+    /// None if there is no way through. Starting out on a blocked tile, e.g. over the lip of a
+    /// pit, still finds the way off it
+    pub fn path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let (start, goal) = (cell(from), cell(to));
+        if start == goal {
+            return Some(vec![to]);
+        }
+
+        if !self.passable(goal) {
+            return None;
+        }
+
+        let mut frontier = BinaryHeap::from([Frontier {
+            cell: start,
+            cost: 0.0,
+            estimate: heuristic(start, goal),
+        }]);
+        let mut costs = HashMap::new();
+        let mut came_from = HashMap::new();
+        costs.insert(start, 0.0);
+
+        while let Some(Frontier { cell, cost, .. }) = frontier.pop() {
+            if cell == goal {
+                let mut waypoints = vec![to];
+                let mut cell = came_from[&goal];
+                while cell != start {
+                    waypoints.push(centre(cell));
+                    cell = came_from[&cell];
+                }
+                waypoints.reverse();
+                return Some(waypoints);
+            }
+
+            // a cheaper way here was found after this one was queued
+            if costs.get(&cell).is_some_and(|best| cost > *best) {
+                continue;
+            }
+
+            for (next, step_cost) in self.neighbours(cell) {
+                let cost = cost + step_cost;
+                if costs.get(&next).map_or(true, |best| cost < *best) {
+                    costs.insert(next, cost);
+                    came_from.insert(next, cell);
+                    frontier.push(Frontier {
+                        cell: next,
+                        cost,
+                        estimate: cost + heuristic(next, goal),
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The tile within reach, in steps, that is furthest from a threat and not beside a pit;
+    /// None if there is nowhere better than where we already are
+    pub fn refuge(&self, from: Vec2, threat: Vec2, reach: u32) -> Option<Vec2> {
+        let start = cell(from);
+        let mut best = (start, from.distance(threat));
+        let mut seen = HashSet::new();
+        seen.insert(start);
+        let mut queue = VecDeque::from([(start, 0)]);
+
+        while let Some((cell, steps)) = queue.pop_front() {
+            let distance = centre(cell).distance(threat);
+            if distance > best.1 && !self.pit_edges.contains(&cell) {
+                best = (cell, distance);
+            }
+
+            if steps == reach {
+                continue;
+            }
+
+            for (next, _) in self.neighbours(cell) {
+                if seen.insert(next) {
+                    queue.push_back((next, steps + 1));
+                }
+            }
+        }
+
+        (best.0 != start).then(|| centre(best.0))
+    }
+}
+
+fn cache_nav_grid(
+    mut grid: ResMut<NavGrid>,
+    mut input: EventReader<CacheEvent>,
+    tiles: Query<(&GridCoords, Option<&Tile>), With<IntGridCell>>,
+) {
+    if input
+        .iter()
+        .any(|event| matches!(event, CacheEvent::InvalidateNavGrid))
+    {
+        *grid = NavGrid::from_tiles(
+            tiles
+                .iter()
+                .map(|(coords, tile)| (IVec2::new(coords.x, coords.y), tile)),
+        );
+    }
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.init_resource::<NavGrid>()
+            .add_systems(PostUpdate, cache_nav_grid);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rows from the top down: '#' is a wall, 'O' a pit and anything else open floor
+    fn grid(rows: &[&str]) -> NavGrid {
+        let top = rows.len() as i32 - 1;
+        NavGrid::from_tiles(rows.iter().enumerate().flat_map(|(row, line)| {
+            line.chars().enumerate().map(move |(column, tile)| {
+                let cell = IVec2::new(column as i32, top - row as i32);
+                match tile {
+                    '#' => (cell, Some(&Tile::Wall)),
+                    'O' => (cell, Some(&Tile::Pit)),
+                    _ => (cell, None),
+                }
+            })
+        }))
+    }
+
+    fn at(x: i32, y: i32) -> Vec2 {
+        centre(IVec2::new(x, y))
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let grid = grid(&[".....", "..#..", "..#.."]);
+
+        let path = grid.path(at(0, 0), at(4, 0)).unwrap();
+
+        assert_eq!(path.last(), Some(&at(4, 0)));
+        assert!(path.iter().all(|waypoint| grid.passable(cell(*waypoint))));
+        assert!(path.iter().any(|waypoint| cell(*waypoint).y == 2));
+    }
+
+    #[test]
+    fn path_to_blocked_goal_is_none() {
+        let grid = grid(&[".....", "..#.."]);
+
+        assert_eq!(grid.path(at(0, 0), at(2, 0)), None);
+    }
+
+    #[test]
+    fn path_to_walled_off_goal_is_none() {
+        let grid = grid(&["..#..", "..#.."]);
+
+        assert_eq!(grid.path(at(0, 0), at(4, 0)), None);
+    }
+
+    #[test]
+    fn path_from_blocked_start_finds_the_way_off() {
+        let grid = grid(&[".....", ".O...", "....."]);
+
+        let path = grid.path(at(1, 1), at(4, 1)).unwrap();
+
+        assert_eq!(path.last(), Some(&at(4, 1)));
+        assert!(path.iter().all(|waypoint| grid.passable(cell(*waypoint))));
+    }
+
+    #[test]
+    fn path_gives_pits_a_wide_berth() {
+        let grid = grid(&[
+            ".........",
+            ".........",
+            ".........",
+            "....O....",
+            ".........",
+        ]);
+
+        // the straight line along the middle row passes right beside the pit
+        let path = grid.path(at(0, 2), at(8, 2)).unwrap();
+
+        assert!(path
+            .iter()
+            .all(|waypoint| !grid.pit_edges.contains(&cell(*waypoint))));
+    }
+
+    #[test]
+    fn refuge_is_further_from_the_threat() {
+        let grid = grid(&[".........", ".........", "........."]);
+        let (from, threat) = (at(4, 1), at(0, 1));
+
+        let refuge = grid.refuge(from, threat, 3).unwrap();
+
+        assert!(refuge.distance(threat) > from.distance(threat));
+        assert!(refuge.distance(from) <= 3.0 * std::f32::consts::SQRT_2 * TILE_SIZE);
+    }
+
+    #[test]
+    fn refuge_is_not_beside_a_pit() {
+        let grid = grid(&["......O", "......."]);
+
+        let refuge = grid.refuge(at(2, 0), at(0, 0), 4).unwrap();
+
+        assert!(!grid.pit_edges.contains(&cell(refuge)));
+    }
+
+    #[test]
+    fn refuge_from_blocked_start_finds_the_way_off() {
+        let grid = grid(&["....", ".O..", "...."]);
+
+        let refuge = grid.refuge(at(1, 1), at(0, 1), 3).unwrap();
+
+        assert!(grid.passable(cell(refuge)));
+        assert!(!grid.pit_edges.contains(&cell(refuge)));
+    }
+
+    #[test]
+    fn refuge_when_boxed_in_is_none() {
+        let grid = grid(&["###", "#.#", "###"]);
+
+        assert_eq!(grid.refuge(at(1, 1), at(0, 1), 3), None);
+    }
+}
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{
+    level,
+    menu::{self, MenuAction, MenuEvent},
+    AppState, OpaquePlugin,
+};
+
+/// Marks the pause overlay's menu
+#[derive(Component)]
+struct PauseScreen;
+
+// like menu navigation, pausing is fixed so that it can't be unbound
+fn pause_game(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let start = gamepads
+        .iter()
+        .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::Start)));
+
+    if keys.just_pressed(KeyCode::Escape) || start {
+        next_state.set(AppState::Paused);
+    }
+}
+
+fn enter_pause(mut commands: Commands) {
+    let items = vec![
+        ("Resume".into(), MenuAction::Resume),
+        ("Restart Level".into(), MenuAction::Restart),
+        ("Quit to Menu".into(), MenuAction::QuitToMenu),
+    ];
+
+    let menu = menu::spawn_menu(&mut commands, "Paused", items, 0);
+    commands.entity(menu).insert(PauseScreen);
+}
+
+fn handle_pause_menu(
+    mut commands: Commands,
+    mut events: EventReader<MenuEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    levels: Query<Entity, With<Handle<LdtkLevel>>>,
+    worlds: Query<Entity, With<Handle<LdtkAsset>>>,
+) {
+    for MenuEvent(action) in events.iter() {
+        match *action {
+            MenuAction::Resume | MenuAction::Back => next_state.set(AppState::Playing),
+            MenuAction::Restart => {
+                if let Ok(level) = levels.get_single() {
+                    level::restart(&mut commands, &mut next_state, level);
+                }
+            }
+            MenuAction::QuitToMenu => {
+                level::unload(&mut commands, &worlds);
+                next_state.set(AppState::Menu);
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.add_systems(
+            Update,
+            (
+                pause_game.run_if(in_state(AppState::Playing)),
+                handle_pause_menu.run_if(in_state(AppState::Paused)),
+            ),
+        )
+        .add_systems(OnEnter(AppState::Paused), enter_pause)
+        .add_systems(OnExit(AppState::Paused), menu::despawn_menus::<PauseScreen>);
+    })
+}
@@ -0,0 +1,139 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::OpaquePlugin;
+
+/// How a thinker chooses between the actions whose scorers are ready
+#[derive(Deserialize, Clone, Debug)]
+pub enum Picker {
+    Highest,
+    FirstToScore { threshold: f32 },
+}
+
+/// Something an orb weighs up; distances are in tiles
+#[derive(Deserialize, Clone, Debug)]
+pub enum Scorer {
+    ExperiencingInertia,
+    NearPit {
+        tiles: f32,
+    },
+    Flee {
+        tiles: f32,
+    },
+    Charge {
+        tiles: f32,
+    },
+    Catchable,
+    InShoveRange {
+        tiles: f32,
+    },
+    PlayerNearPit,
+    LostSight,
+    /// every scorer multiplied together, or nothing if that comes to less than the threshold
+    Product {
+        threshold: f32,
+        scorers: Vec<Scorer>,
+    },
+}
+
+/// Something an orb does about it; distances are in tiles
+#[derive(Deserialize, Clone, Debug)]
+pub enum Action {
+    Halt,
+    AvoidPit {
+        tiles: f32,
+    },
+    AvoidPlayer {
+        tiles: f32,
+    },
+    ChasePlayer {
+        tiles: f32,
+    },
+    SearchPlayer {
+        tiles: f32,
+    },
+    ShovePlayer,
+    RamPlayer,
+    /// a nested thinker, with its own picker and choices
+    Think(Box<Thinking>),
+}
+
+/// A scorer and what to do when it's picked
+#[derive(Deserialize, Clone, Debug)]
+pub struct Choice {
+    pub when: Scorer,
+    pub then: Action,
+}
+
+/// One level of a personality's decision tree
+#[derive(Deserialize, Clone, Debug)]
+pub struct Thinking {
+    pub picker: Picker,
+    pub choices: Vec<Choice>,
+    /// what to do when nothing is picked
+    #[serde(default)]
+    pub otherwise: Option<Action>,
+}
+
+/// Named personalities, loaded from a .personalities.ron file
+#[derive(Deserialize, TypeUuid, TypePath, Debug)]
+#[uuid = "0f3d9a71-6b2e-4c58-8e14-a9d27c5b3f60"]
+pub struct Personalities(HashMap<String, Thinking>);
+
+impl Personalities {
+    pub fn get(&self, name: &str) -> Option<&Thinking> {
+        self.0.get(name)
+    }
+}
+
+#[derive(Default)]
+struct PersonalitiesLoader;
+
+impl AssetLoader for PersonalitiesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let personalities: Personalities = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(personalities));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["personalities.ron"]
+    }
+}
+
+/// The personalities enemies are given by name
+#[derive(Resource)]
+pub struct DefaultPersonalities(pub Handle<Personalities>);
+
+pub fn personalities_loaded(
+    defaults: Res<DefaultPersonalities>,
+    assets: Res<Assets<Personalities>>,
+) -> bool {
+    assets.contains(&defaults.0)
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(DefaultPersonalities(
+        asset_server.load("orbs.personalities.ron"),
+    ));
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.add_asset::<Personalities>()
+            .init_asset_loader::<PersonalitiesLoader>()
+            .add_systems(Startup, setup);
+    })
+}
@@ -0,0 +1,270 @@
+use anyhow::{bail, Context};
+use bevy::{app::AppExit, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    controls::Seat, versus, AppState, InputEvent, Intent, OpaquePlugin, OutcomeEvent, Tick, TickSet,
+};
+
+// bump when the meaning of existing fields changes
+const REPLAY_VERSION: u32 = 1;
+
+/// Everything needed to reproduce one attempt at a level
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Replay {
+    version: u32,
+    pub level: usize,
+    inputs: Vec<ReplayInput>,
+    outcome: Option<ReplayOutcome>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct ReplayInput {
+    tick: u32,
+    // replays from before versus mode only ever had one seat
+    #[serde(default)]
+    seat: Seat,
+    event: ReplayEvent,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+enum ReplayEvent {
+    Decelerate,
+    Accelerate([f32; 2]),
+    Charge,
+    Rewind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct ReplayOutcome {
+    tick: u32,
+    victory: bool,
+}
+
+impl From<Intent> for ReplayEvent {
+    fn from(value: Intent) -> Self {
+        match value {
+            Intent::Decelerate => ReplayEvent::Decelerate,
+            Intent::Accelerate(thrust) => ReplayEvent::Accelerate(thrust.to_array()),
+            Intent::Charge => ReplayEvent::Charge,
+            Intent::Rewind => ReplayEvent::Rewind,
+        }
+    }
+}
+
+impl From<ReplayEvent> for Intent {
+    fn from(value: ReplayEvent) -> Self {
+        match value {
+            ReplayEvent::Decelerate => Intent::Decelerate,
+            ReplayEvent::Accelerate(thrust) => Intent::Accelerate(Vec2::from_array(thrust)),
+            ReplayEvent::Charge => Intent::Charge,
+            ReplayEvent::Rewind => Intent::Rewind,
+        }
+    }
+}
+
+impl ReplayOutcome {
+    fn new(tick: &Tick, event: &OutcomeEvent) -> Self {
+        ReplayOutcome {
+            tick: tick.0,
+            victory: matches!(event, OutcomeEvent::Victory),
+        }
+    }
+}
+
+impl Replay {
+    fn new(level: usize) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            level,
+            inputs: Vec::new(),
+            outcome: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Replay> {
+        let file =
+            std::fs::File::open(path).with_context(|| format!("open replay {}", path.display()))?;
+        let replay: Replay = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("deserialise replay {}", path.display()))?;
+
+        if replay.version > REPLAY_VERSION {
+            bail!(
+                "replay {} is version {}, but only up to {} is supported",
+                path.display(),
+                replay.version,
+                REPLAY_VERSION
+            );
+        }
+
+        Ok(replay)
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("create replay {}", path.display()))?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)
+            .with_context(|| format!("serialise replay {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Captures inputs for --record, restarting with each level attempt; versus rounds are left out,
+/// since playback is always single player
+#[derive(Resource)]
+struct Recorder {
+    path: PathBuf,
+    replay: Replay,
+}
+
+/// Feeds inputs from --replay in place of the keyboard
+#[derive(Resource)]
+pub struct Playback {
+    replay: Replay,
+    cursor: usize,
+    matched: Option<bool>,
+}
+
+impl Playback {
+    /// Whether the first outcome reproduced the recording, once there has been one
+    pub fn matched(&self) -> Option<bool> {
+        self.matched
+    }
+}
+
+fn restart_recording(
+    mut recorder: ResMut<Recorder>,
+    mut level_events: EventReader<LevelEvent>,
+    level: Res<LevelSelection>,
+) {
+    for level_event in level_events.iter() {
+        if let LevelEvent::Spawned(_) = level_event {
+            let index = match level.as_ref() {
+                LevelSelection::Index(i) => *i,
+                _ => recorder.replay.level,
+            };
+            recorder.replay = Replay::new(index);
+        }
+    }
+}
+
+fn record_input(
+    tick: Res<Tick>,
+    mut recorder: ResMut<Recorder>,
+    mut events: EventReader<InputEvent>,
+) {
+    for event in events.iter() {
+        recorder.replay.inputs.push(ReplayInput {
+            tick: tick.0,
+            seat: event.seat,
+            event: event.intent.into(),
+        });
+    }
+}
+
+fn record_outcome(
+    tick: Res<Tick>,
+    mut recorder: ResMut<Recorder>,
+    mut outcomes: EventReader<OutcomeEvent>,
+) -> anyhow::Result<()> {
+    if let Some(event) = outcomes.iter().next() {
+        if recorder.replay.outcome.is_none() {
+            recorder.replay.outcome = Some(ReplayOutcome::new(&tick, event));
+            recorder.replay.save(&recorder.path)?;
+            info!("Recorded replay {}", recorder.path.display());
+        }
+    }
+    Ok(())
+}
+
+// an unfinished attempt is still worth keeping, e.g. if the player quit after spotting a bug
+fn record_on_exit(recorder: Res<Recorder>, mut exits: EventReader<AppExit>) -> anyhow::Result<()> {
+    if exits.iter().next().is_some() && recorder.replay.outcome.is_none() {
+        recorder.replay.save(&recorder.path)?;
+        info!("Recorded unfinished replay {}", recorder.path.display());
+    }
+    Ok(())
+}
+
+fn playback_input(
+    tick: Res<Tick>,
+    mut playback: ResMut<Playback>,
+    mut events: EventWriter<InputEvent>,
+) {
+    while let Some(input) = playback.replay.inputs.get(playback.cursor).copied() {
+        if input.tick > tick.0 {
+            break;
+        }
+
+        if input.tick == tick.0 {
+            events.send(InputEvent::new(input.seat, input.event.into()));
+        }
+
+        playback.cursor += 1;
+    }
+}
+
+fn verify_outcome(
+    tick: Res<Tick>,
+    mut playback: ResMut<Playback>,
+    mut outcomes: EventReader<OutcomeEvent>,
+) {
+    if let Some(event) = outcomes.iter().next() {
+        if playback.matched.is_none() {
+            let actual = ReplayOutcome::new(&tick, event);
+            let matched = playback.replay.outcome == Some(actual);
+            if matched {
+                info!("Replay reproduced {actual:?}");
+            } else {
+                warn!(
+                    "Replay diverged: expected {:?}, got {actual:?}",
+                    playback.replay.outcome
+                );
+            }
+            playback.matched = Some(matched);
+        }
+    }
+}
+
+pub fn plugin(record: Option<PathBuf>, playback: Option<Replay>) -> impl Plugin {
+    OpaquePlugin(move |app| {
+        if let Some(path) = &record {
+            app.insert_resource(Recorder {
+                path: path.clone(),
+                replay: Replay::new(0),
+            })
+            .add_systems(
+                Update,
+                restart_recording.run_if(not(resource_exists::<versus::Versus>())),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    record_input.after(TickSet::Input),
+                    record_outcome.pipe(super::handle).after(TickSet::Outcome),
+                )
+                    .run_if(in_state(AppState::Playing))
+                    .run_if(not(resource_exists::<versus::Versus>())),
+            )
+            .add_systems(Last, record_on_exit.pipe(super::handle));
+        }
+
+        if let Some(replay) = &playback {
+            app.insert_resource(Playback {
+                replay: replay.clone(),
+                cursor: 0,
+                matched: None,
+            })
+            .add_systems(
+                FixedUpdate,
+                (
+                    playback_input.in_set(TickSet::Input),
+                    verify_outcome.after(TickSet::Outcome),
+                )
+                    .run_if(in_state(AppState::Playing)),
+            );
+        }
+    })
+}
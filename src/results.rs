@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{
+    level,
+    menu::{self, MenuAction, MenuEvent},
+    save::LevelRecord,
+    AppState, Attempt, OpaquePlugin, Tick, TIMESTEP,
+};
+
+/// Marks the menu shown after a victory
+#[derive(Component)]
+struct ResultsScreen;
+
+/// How the most recent victory went, compared with the best before it
+#[derive(Resource, Default)]
+pub struct Results {
+    /// iid of the level that was beaten
+    pub level: String,
+    pub name: String,
+    /// seconds, counted in simulation ticks
+    pub time: f32,
+    pub thrusts: u32,
+    pub knocked: u32,
+    pub stars: u32,
+    /// out of how many the level has to give
+    pub max_stars: u32,
+    pub new_best_time: bool,
+    pub new_best_thrusts: bool,
+    pub new_best_stars: bool,
+}
+
+impl Results {
+    pub fn score(
+        level: Option<&ldtk::Level>,
+        tick: &Tick,
+        attempt: &Attempt,
+        record: Option<&LevelRecord>,
+    ) -> Self {
+        let time = tick.0 as f32 * TIMESTEP.as_secs_f32();
+        let par = level.map(level::par);
+        let stars = par
+            .as_ref()
+            .map_or(1, |par| stars(par, time, attempt.thrusts));
+
+        Results {
+            level: attempt.level.clone(),
+            name: level.map(level::level_name).unwrap_or_default(),
+            time,
+            thrusts: attempt.thrusts,
+            knocked: attempt.knocked,
+            stars,
+            max_stars: par.as_ref().map_or(1, level::Par::max_stars),
+            new_best_time: record
+                .and_then(|record| record.best_time)
+                .is_none_or(|best| time < best),
+            new_best_thrusts: record
+                .and_then(|record| record.fewest_thrusts)
+                .is_none_or(|best| attempt.thrusts < best),
+            new_best_stars: record
+                .and_then(|record| record.best_stars)
+                .is_none_or(|best| stars > best),
+        }
+    }
+}
+
+/// One star for winning, plus one for each par met; a level with no par for something has no
+/// star for it, rather than giving it away
+pub fn stars(par: &level::Par, time: f32, thrusts: u32) -> u32 {
+    let mut stars = 1;
+
+    if par.time.is_some_and(|par| time <= par) {
+        stars += 1;
+    }
+
+    if par.thrusts.is_some_and(|par| thrusts <= par) {
+        stars += 1;
+    }
+
+    stars
+}
+
+/// Filled and empty stars out of those the level has to give, in characters the default font has
+pub fn stars_label(stars: u32, max_stars: u32) -> String {
+    let stars = stars.min(max_stars) as usize;
+    format!(
+        "{}{}",
+        "*".repeat(stars),
+        "-".repeat(max_stars as usize - stars)
+    )
+}
+
+fn enter_results(mut commands: Commands, results: Res<Results>) {
+    let best = |new_best: bool| if new_best { " - new best!" } else { "" };
+
+    let items = vec![
+        (
+            format!("Time: {:.2}s{}", results.time, best(results.new_best_time)),
+            MenuAction::Disabled,
+        ),
+        (
+            format!(
+                "Thrusts: {}{}",
+                results.thrusts,
+                best(results.new_best_thrusts)
+            ),
+            MenuAction::Disabled,
+        ),
+        (
+            format!("Enemies knocked in: {}", results.knocked),
+            MenuAction::Disabled,
+        ),
+        (
+            format!(
+                "Stars: {}{}",
+                stars_label(results.stars, results.max_stars),
+                best(results.new_best_stars)
+            ),
+            MenuAction::Disabled,
+        ),
+        ("Next Level".into(), MenuAction::NextLevel),
+        ("Retry".into(), MenuAction::Restart),
+        ("Quit to Menu".into(), MenuAction::QuitToMenu),
+    ];
+
+    // start on "Next Level", past the scores
+    let menu = menu::spawn_menu(&mut commands, &results.name, items, 4);
+    commands.entity(menu).insert(ResultsScreen);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_results_menu(
+    mut commands: Commands,
+    mut events: EventReader<MenuEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    results: Res<Results>,
+    project: Res<level::LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+    levels: Query<Entity, With<Handle<LdtkLevel>>>,
+    worlds: Query<Entity, With<Handle<LdtkAsset>>>,
+) {
+    for MenuEvent(action) in events.iter() {
+        match *action {
+            MenuAction::NextLevel => level::advance(
+                &mut commands,
+                &mut next_state,
+                assets.get(&project.0),
+                &results.level,
+            ),
+            MenuAction::Restart => {
+                if let Ok(level) = levels.get_single() {
+                    level::restart(&mut commands, &mut next_state, level);
+                }
+            }
+            MenuAction::QuitToMenu => {
+                level::unload(&mut commands, &worlds);
+                next_state.set(AppState::Menu);
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.init_resource::<Results>()
+            .add_systems(
+                Update,
+                handle_results_menu.run_if(in_state(AppState::Results)),
+            )
+            .add_systems(OnEnter(AppState::Results), enter_results)
+            .add_systems(
+                OnExit(AppState::Results),
+                menu::despawn_menus::<ResultsScreen>,
+            );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn par(time: Option<f32>, thrusts: Option<u32>) -> level::Par {
+        level::Par { time, thrusts }
+    }
+
+    #[test]
+    fn stars_for_each_par_met() {
+        let par = par(Some(10.0), Some(5));
+
+        assert_eq!(stars(&par, 9.0, 4), 3);
+        assert_eq!(stars(&par, 10.0, 5), 3);
+        assert_eq!(stars(&par, 11.0, 5), 2);
+        assert_eq!(stars(&par, 11.0, 6), 1);
+        assert_eq!(par.max_stars(), 3);
+    }
+
+    #[test]
+    fn stars_not_given_away_for_missing_par() {
+        let time_only = par(Some(10.0), None);
+        assert_eq!(stars(&time_only, 9.0, 0), 2);
+        assert_eq!(time_only.max_stars(), 2);
+
+        let thrusts_only = par(None, Some(5));
+        assert_eq!(stars(&thrusts_only, 0.0, 5), 2);
+        assert_eq!(thrusts_only.max_stars(), 2);
+
+        let neither = par(None, None);
+        assert_eq!(stars(&neither, 0.0, 0), 1);
+        assert_eq!(neither.max_stars(), 1);
+    }
+
+    #[test]
+    fn stars_label_out_of_the_level_max() {
+        assert_eq!(stars_label(2, 3), "**-");
+        assert_eq!(stars_label(1, 1), "*");
+        // a best from before a par was taken away still fits
+        assert_eq!(stars_label(3, 2), "**");
+    }
+}
@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_tweening::Animator;
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    collision, level::Enemy, versus, AppState, Attempt, CacheEvent, Falling, InputEvent, Intent,
+    OpaquePlugin, Orb, Tick, TickSet, TIMESTEP,
+};
+
+// how far back a rewind can go
+const HISTORY: Duration = Duration::from_secs(5);
+const HISTORY_TICKS: usize = (HISTORY.as_nanos() / TIMESTEP.as_nanos()) as usize;
+
+/// Everything needed to bring an orb back out of a pit
+#[derive(Component)]
+pub struct Rewindable {
+    pub orb: Orb,
+    pub mass: f32,
+    pub restitution: f32,
+}
+
+/// All the way into a pit at the given tick; out of play, but kept while it could be rewound
+#[derive(Component)]
+pub struct Fallen(pub u32);
+
+/// Each orb's pose at the end of one tick, or None if it was in a pit
+type Snapshot = Vec<(Entity, Option<(Transform, Velocity)>)>;
+
+/// Recent ticks, newest last
+#[derive(Resource, Default)]
+struct History(VecDeque<Snapshot>);
+
+/// Set on ticks when the player is rewinding, which aren't themselves recorded
+#[derive(Resource, Default)]
+struct Rewinding(bool);
+
+fn clear_history(mut history: ResMut<History>, mut rewinding: ResMut<Rewinding>) {
+    history.0.clear();
+    rewinding.0 = false;
+}
+
+fn record_history(
+    mut commands: Commands,
+    tick: Res<Tick>,
+    rewinding: Res<Rewinding>,
+    mut history: ResMut<History>,
+    orbs: Query<(Entity, &Transform, &Velocity, Has<Orb>), With<Rewindable>>,
+    fallen: Query<(Entity, &Fallen)>,
+) {
+    if rewinding.0 {
+        return;
+    }
+
+    history.0.push_back(
+        orbs.iter()
+            .map(|(entity, transform, velocity, present)| {
+                (entity, present.then_some((*transform, *velocity)))
+            })
+            .collect(),
+    );
+
+    if history.0.len() > HISTORY_TICKS {
+        history.0.pop_front();
+    }
+
+    // once out of reach, fallen orbs are gone for good
+    for (entity, fallen) in fallen.iter() {
+        if tick.0.saturating_sub(fallen.0) as usize > HISTORY_TICKS {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// runs after everything else has moved, so that the past overrides it
+#[allow(clippy::type_complexity)]
+fn rewind_orbs(
+    mut commands: Commands,
+    mut events: EventReader<InputEvent>,
+    mut rewinding: ResMut<Rewinding>,
+    mut history: ResMut<History>,
+    mut attempt: ResMut<Attempt>,
+    mut cache_events: EventWriter<CacheEvent>,
+    mut orbs: Query<(
+        &mut Transform,
+        &mut Velocity,
+        &mut ExternalImpulse,
+        &Rewindable,
+        Has<Orb>,
+        Has<Enemy>,
+    )>,
+) {
+    rewinding.0 = events
+        .iter()
+        .any(|event| matches!(event.intent, Intent::Rewind));
+
+    if !rewinding.0 {
+        return;
+    }
+
+    // the oldest tick is kept, so that holding on at the end of history holds still there
+    let snapshot = if history.0.len() > 1 {
+        history.0.pop_back()
+    } else {
+        history.0.back().cloned()
+    };
+
+    let Some(snapshot) = snapshot else {
+        return;
+    };
+
+    for (entity, pose) in snapshot {
+        let Some((past_transform, past_velocity)) = pose else {
+            continue;
+        };
+
+        let Ok((mut transform, mut velocity, mut impulse, rewindable, present, enemy)) =
+            orbs.get_mut(entity)
+        else {
+            continue;
+        };
+
+        *transform = past_transform;
+        *velocity = past_velocity;
+        *impulse = ExternalImpulse::default();
+
+        // back out of the pit, undoing everything that falling in did
+        if !present {
+            commands
+                .entity(entity)
+                .remove::<(Falling, Fallen, Animator<Transform>, RigidBodyDisabled)>()
+                .insert(rewindable.orb.clone())
+                .insert(Visibility::Inherited)
+                .despawn_descendants()
+                .with_children(|children| {
+                    collision::spawn_orb(
+                        children,
+                        rewindable.mass,
+                        rewindable.orb.radius,
+                        rewindable.restitution,
+                    )
+                });
+
+            if enemy {
+                attempt.knocked = attempt.knocked.saturating_sub(1);
+            }
+
+            cache_events.send(CacheEvent::InvalidateColliderHierarchy);
+        }
+    }
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.init_resource::<History>()
+            .init_resource::<Rewinding>()
+            .add_systems(OnEnter(AppState::Loading), clear_history)
+            .add_systems(
+                FixedUpdate,
+                (
+                    // it would undo the other player's moves too
+                    rewind_orbs
+                        .run_if(not(resource_exists::<versus::Versus>()))
+                        .in_set(TickSet::Movement)
+                        .after(super::cap_velocity)
+                        .after(super::trigger_vfx),
+                    record_history.after(TickSet::Outcome),
+                )
+                    .run_if(in_state(AppState::Playing)),
+            );
+    })
+}
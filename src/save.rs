@@ -0,0 +1,283 @@
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{level, results::Results, AppState, OpaquePlugin, OutcomeEvent, TickSet};
+
+// bump when the meaning of existing fields changes; new fields just need a default
+const SAVE_VERSION: u32 = 1;
+
+/// Everything remembered between sessions
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+struct SaveData {
+    version: u32,
+    /// keyed by iid, which survives levels being renamed or reordered
+    levels: BTreeMap<String, LevelRecord>,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        SaveData {
+            version: SAVE_VERSION,
+            levels: BTreeMap::new(),
+        }
+    }
+}
+
+/// The player's history with one level
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LevelRecord {
+    pub unlocked: bool,
+    pub completed: bool,
+    /// seconds, counted in simulation ticks
+    pub best_time: Option<f32>,
+    pub fewest_thrusts: Option<u32>,
+    pub best_stars: Option<u32>,
+}
+
+/// Save data, persisted in the user's data dir unless this is an unattended run
+#[derive(Resource)]
+pub struct Progress {
+    path: Option<PathBuf>,
+    data: SaveData,
+}
+
+impl Progress {
+    fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("shoveit").join("save.json"))
+    }
+
+    fn load(persistent: bool) -> Progress {
+        let path = if persistent { Progress::path() } else { None };
+
+        match path {
+            Some(path) => Progress::load_from(path),
+            None => Progress {
+                path: None,
+                data: SaveData::default(),
+            },
+        }
+    }
+
+    fn load_from(path: PathBuf) -> Progress {
+        let data = match std::fs::read_to_string(&path) {
+            Ok(text) => match serde_json::from_str::<SaveData>(&text) {
+                Ok(data) if data.version > SAVE_VERSION => {
+                    // fields this version doesn't know about would be lost by writing it back
+                    warn!(
+                        "{} is version {}, but only up to {} is supported; progress will not be saved",
+                        path.display(),
+                        data.version,
+                        SAVE_VERSION
+                    );
+                    return Progress { path: None, data };
+                }
+                Ok(data) => data,
+                Err(cause) => {
+                    warn!("ignoring {}: {}", path.display(), cause);
+                    SaveData::default()
+                }
+            },
+            Err(_) => SaveData::default(),
+        };
+
+        Progress {
+            path: Some(path),
+            data,
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context("create data directory")?;
+        }
+
+        write_atomically(path, |writer| {
+            serde_json::to_writer_pretty(writer, &self.data).context("serialise SaveData")
+        })
+    }
+
+    pub fn level(&self, iid: &str) -> Option<&LevelRecord> {
+        self.data.levels.get(iid)
+    }
+
+    /// The first level is always open; the rest must be reached by winning the one before
+    pub fn is_unlocked(&self, index: usize, iid: &str) -> bool {
+        index == 0 || self.level(iid).is_some_and(|record| record.unlocked)
+    }
+
+    fn record_victory(&mut self, results: &Results) {
+        let record = self.data.levels.entry(results.level.clone()).or_default();
+        record.unlocked = true;
+        record.completed = true;
+        record.best_time = Some(
+            record
+                .best_time
+                .map_or(results.time, |best| best.min(results.time)),
+        );
+        record.fewest_thrusts = Some(
+            record
+                .fewest_thrusts
+                .map_or(results.thrusts, |best| best.min(results.thrusts)),
+        );
+        record.best_stars = Some(
+            record
+                .best_stars
+                .map_or(results.stars, |best| best.max(results.stars)),
+        );
+    }
+
+    fn unlock(&mut self, iid: &str) {
+        self.data.levels.entry(iid.to_owned()).or_default().unlocked = true;
+    }
+}
+
+/// Writes a file via a temporary one renamed over it, so that a crash mid-write leaves the
+/// original intact
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+    let file =
+        File::create(&temp_path).with_context(|| format!("create {}", temp_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer
+        .get_ref()
+        .sync_all()
+        .with_context(|| format!("sync {}", temp_path.display()))?;
+
+    std::fs::rename(&temp_path, path).with_context(|| format!("replace {}", path.display()))?;
+    Ok(())
+}
+
+// runs after the results have been scored against the previous bests
+fn save_victory(
+    results: Res<Results>,
+    project: Res<level::LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+    mut progress: ResMut<Progress>,
+    mut outcomes: EventReader<OutcomeEvent>,
+) -> anyhow::Result<()> {
+    if !outcomes
+        .iter()
+        .any(|outcome| matches!(outcome, OutcomeEvent::Victory))
+    {
+        return Ok(());
+    }
+
+    progress.record_victory(&results);
+
+    if let Some((_, next)) = assets
+        .get(&project.0)
+        .and_then(|project| level::next_level(project, &results.level))
+    {
+        progress.unlock(&next.iid);
+    }
+
+    progress.save()
+}
+
+pub fn plugin(persistent: bool) -> impl Plugin {
+    OpaquePlugin(move |app| {
+        app.insert_resource(Progress::load(persistent)).add_systems(
+            FixedUpdate,
+            save_victory
+                .pipe(super::handle)
+                .after(TickSet::Outcome)
+                .run_if(in_state(AppState::Playing)),
+        );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory per test, since they run in parallel
+    fn temp_save(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("shoveit-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("save.json")
+    }
+
+    #[test]
+    fn save_round_trip() {
+        let path = temp_save("round-trip");
+        let mut progress = Progress {
+            path: Some(path.clone()),
+            data: SaveData::default(),
+        };
+
+        progress.record_victory(&Results {
+            level: "first".into(),
+            time: 12.5,
+            thrusts: 7,
+            stars: 2,
+            ..default()
+        });
+        progress.unlock("second");
+        progress.save().unwrap();
+
+        let loaded = Progress::load_from(path.clone());
+        let record = loaded.level("first").unwrap();
+        assert!(record.completed);
+        assert_eq!(record.best_time, Some(12.5));
+        assert_eq!(record.fewest_thrusts, Some(7));
+        assert_eq!(record.best_stars, Some(2));
+        assert!(loaded.is_unlocked(1, "second"));
+        assert!(!loaded.is_unlocked(2, "third"));
+        assert_eq!(loaded.path, Some(path.clone()));
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn save_from_newer_version_is_left_alone() {
+        let path = temp_save("newer-version");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let text = format!(
+            r#"{{"version": {}, "levels": {{"first": {{"completed": true}}}}, "extra": 1}}"#,
+            SAVE_VERSION + 1
+        );
+        std::fs::write(&path, &text).unwrap();
+
+        // still readable, but never written back
+        let progress = Progress::load_from(path.clone());
+        assert!(progress.level("first").unwrap().completed);
+        assert_eq!(progress.path, None);
+
+        progress.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn unreadable_save_starts_afresh() {
+        let path = temp_save("unreadable");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "not json").unwrap();
+
+        let progress = Progress::load_from(path.clone());
+        assert!(progress.level("first").is_none());
+        assert_eq!(progress.path, Some(path.clone()));
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
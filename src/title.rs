@@ -0,0 +1,251 @@
+use bevy::{app::AppExit, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{
+    controls::ControlsReturn,
+    level,
+    menu::{self, Menu, MenuAction, MenuEvent},
+    results,
+    save::Progress,
+    versus, AppState, OpaquePlugin,
+};
+
+/// Marks the title screen's menu
+#[derive(Component)]
+struct TitleScreen;
+
+/// Marks the level select menu
+#[derive(Component)]
+struct LevelSelectScreen;
+
+/// Marks the menu shown after the last level
+#[derive(Component)]
+struct FinishedScreen;
+
+// versus needs a level with a spawn point for each player, so is disabled until there's one
+fn spawn_title(commands: &mut Commands, project: Option<&LdtkAsset>, selected: usize) {
+    let versus = match project.map(level::versus_levels) {
+        Some(arenas) if !arenas.is_empty() => MenuAction::Versus,
+        _ => MenuAction::Disabled,
+    };
+
+    let items = vec![
+        ("Continue".into(), MenuAction::Continue),
+        ("Level Select".into(), MenuAction::LevelSelect),
+        ("Versus".into(), versus),
+        ("Settings".into(), MenuAction::Settings),
+        ("Quit".into(), MenuAction::Quit),
+    ];
+
+    let menu = menu::spawn_menu(commands, "Shove it!", items, selected);
+    commands.entity(menu).insert(TitleScreen);
+}
+
+fn enter_title(
+    mut commands: Commands,
+    project: Res<level::LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+) {
+    spawn_title(&mut commands, assets.get(&project.0), 0);
+}
+
+// the project usually finishes loading after the title screen is first shown
+fn refresh_title(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<LdtkAsset>>,
+    project: Res<level::LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+    menus: Query<(Entity, &Menu), With<TitleScreen>>,
+) {
+    let loaded = events.iter().any(|event| {
+        matches!(event, AssetEvent::Created { handle } | AssetEvent::Modified { handle }
+            if *handle == project.0)
+    });
+    if !loaded {
+        return;
+    }
+
+    for (entity, menu) in menus.iter() {
+        let selected = menu.selected();
+        commands.entity(entity).despawn_recursive();
+        spawn_title(&mut commands, assets.get(&project.0), selected);
+    }
+}
+
+// the earliest level still to be beaten, or failing that the last one reached
+fn continue_level(project: &LdtkAsset, progress: &Progress) -> usize {
+    let mut last_unlocked = 0;
+    for (index, level) in project.iter_levels().enumerate() {
+        if !progress.is_unlocked(index, &level.iid) {
+            continue;
+        }
+
+        if !progress
+            .level(&level.iid)
+            .is_some_and(|record| record.completed)
+        {
+            return index;
+        }
+
+        last_unlocked = index;
+    }
+    last_unlocked
+}
+
+fn handle_title_menu(
+    mut commands: Commands,
+    mut events: EventReader<MenuEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exits: EventWriter<AppExit>,
+    project: Res<level::LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+    progress: Res<Progress>,
+) {
+    // both need to know what levels exist, which is only known once the project has loaded
+    let project = assets.get(&project.0);
+
+    for MenuEvent(action) in events.iter() {
+        match *action {
+            MenuAction::Continue => {
+                if let Some(project) = project {
+                    let index = continue_level(project, &progress);
+                    commands.insert_resource(LevelSelection::Index(index));
+                    next_state.set(AppState::Loading);
+                }
+            }
+            MenuAction::LevelSelect if project.is_some() => {
+                next_state.set(AppState::LevelSelect);
+            }
+            MenuAction::Versus => {
+                if let Some(project) = project {
+                    versus::start(&mut commands, &mut next_state, project);
+                }
+            }
+            MenuAction::Settings => {
+                commands.insert_resource(ControlsReturn(AppState::Menu));
+                next_state.set(AppState::Controls);
+            }
+            MenuAction::Quit => exits.send(AppExit),
+            _ => (),
+        }
+    }
+}
+
+fn describe_level(index: usize, level: &ldtk::Level, progress: &Progress) -> (String, MenuAction) {
+    let name = level::level_name(level);
+
+    if !progress.is_unlocked(index, &level.iid) {
+        return (format!("{name} - locked"), MenuAction::Disabled);
+    }
+
+    let label = match progress.level(&level.iid).filter(|record| record.completed) {
+        Some(record) => format!(
+            "{name} {} - {:.2}s, {} thrusts",
+            results::stars_label(
+                record.best_stars.unwrap_or(1),
+                level::par(level).max_stars()
+            ),
+            record.best_time.unwrap_or_default(),
+            record.fewest_thrusts.unwrap_or_default()
+        ),
+        None => name,
+    };
+
+    (label, MenuAction::StartLevel(index))
+}
+
+fn enter_level_select(
+    mut commands: Commands,
+    project: Res<level::LevelProject>,
+    assets: Res<Assets<LdtkAsset>>,
+    progress: Res<Progress>,
+) {
+    let mut items = Vec::new();
+    let mut selected = 0;
+
+    if let Some(project) = assets.get(&project.0) {
+        for chapter in level::chapters(project) {
+            if let Some(name) = chapter.name {
+                items.push((format!("- {name} -"), MenuAction::Disabled));
+            }
+
+            for (index, level) in chapter.levels {
+                items.push(describe_level(index, level, &progress));
+            }
+        }
+
+        let current = MenuAction::StartLevel(continue_level(project, &progress));
+        selected = items
+            .iter()
+            .position(|(_, action)| *action == current)
+            .unwrap_or_default();
+    }
+
+    items.push(("Back".into(), MenuAction::Back));
+
+    let menu = menu::spawn_menu(&mut commands, "Level Select", items, selected);
+    commands.entity(menu).insert(LevelSelectScreen);
+}
+
+fn handle_level_select_menu(
+    mut commands: Commands,
+    mut events: EventReader<MenuEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for MenuEvent(action) in events.iter() {
+        match *action {
+            MenuAction::StartLevel(i) => {
+                commands.insert_resource(LevelSelection::Index(i));
+                next_state.set(AppState::Loading);
+            }
+            MenuAction::Back => next_state.set(AppState::Menu),
+            _ => (),
+        }
+    }
+}
+
+fn enter_finished(mut commands: Commands) {
+    let items = vec![("Back to Menu".into(), MenuAction::QuitToMenu)];
+
+    let menu = menu::spawn_menu(&mut commands, "The End", items, 0);
+    commands.entity(menu).insert(FinishedScreen);
+}
+
+fn handle_finished_menu(
+    mut commands: Commands,
+    mut events: EventReader<MenuEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    worlds: Query<Entity, With<Handle<LdtkAsset>>>,
+) {
+    for MenuEvent(action) in events.iter() {
+        if let MenuAction::QuitToMenu | MenuAction::Back = *action {
+            level::unload(&mut commands, &worlds);
+            next_state.set(AppState::Menu);
+        }
+    }
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.add_systems(
+            Update,
+            (
+                (handle_title_menu, refresh_title).run_if(in_state(AppState::Menu)),
+                handle_level_select_menu.run_if(in_state(AppState::LevelSelect)),
+                handle_finished_menu.run_if(in_state(AppState::Finished)),
+            ),
+        )
+        .add_systems(OnEnter(AppState::Menu), enter_title)
+        .add_systems(OnExit(AppState::Menu), menu::despawn_menus::<TitleScreen>)
+        .add_systems(OnEnter(AppState::LevelSelect), enter_level_select)
+        .add_systems(
+            OnExit(AppState::LevelSelect),
+            menu::despawn_menus::<LevelSelectScreen>,
+        )
+        .add_systems(OnEnter(AppState::Finished), enter_finished)
+        .add_systems(
+            OnExit(AppState::Finished),
+            menu::despawn_menus::<FinishedScreen>,
+        );
+    })
+}
@@ -0,0 +1,191 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{
+    controls::Seat,
+    level,
+    menu::{self, MenuAction, MenuEvent},
+    AppState, Falling, OpaquePlugin, Orb, PlayerInput, TickSet,
+};
+
+// first to this many rounds takes the match
+const ROUNDS_TO_WIN: u32 = 3;
+
+/// Marks the menu shown once a match has been won
+#[derive(Component)]
+struct MatchOverScreen;
+
+/// Present while a versus match is on, in place of the usual level outcomes
+#[derive(Resource)]
+pub struct Versus {
+    /// indices of the levels with a spawn point for each player, played in turn
+    arenas: Vec<usize>,
+    round: usize,
+    wins: [u32; Seat::COUNT],
+}
+
+impl Versus {
+    fn new(arenas: Vec<usize>) -> Self {
+        Versus {
+            arenas,
+            round: 0,
+            wins: [0; Seat::COUNT],
+        }
+    }
+
+    /// Counting from zero
+    pub fn round(&self) -> usize {
+        self.round
+    }
+
+    pub fn score_label(&self) -> String {
+        format!(
+            "P1 {} - {} P2",
+            self.wins[Seat::One.index()],
+            self.wins[Seat::Two.index()]
+        )
+    }
+
+    fn arena(&self) -> LevelSelection {
+        LevelSelection::Index(self.arenas[self.round % self.arenas.len()])
+    }
+
+    fn winner(&self) -> Option<Seat> {
+        [Seat::One, Seat::Two]
+            .into_iter()
+            .find(|seat| self.wins[seat.index()] >= ROUNDS_TO_WIN)
+    }
+}
+
+/// Begins a match on the first level with a spawn point for each player, if there are any
+pub fn start(commands: &mut Commands, next_state: &mut NextState<AppState>, project: &LdtkAsset) {
+    let arenas = level::versus_levels(project);
+    if arenas.is_empty() {
+        warn!("no levels have a spawn point for a second player");
+        return;
+    }
+
+    let versus = Versus::new(arenas);
+    commands.insert_resource(versus.arena());
+    commands.insert_resource(versus);
+    next_state.set(AppState::Loading);
+}
+
+// the arena for the next round may be the one just played, which has to be respawned
+fn next_round(
+    commands: &mut Commands,
+    next_state: &mut NextState<AppState>,
+    versus: &Versus,
+    selection: &LevelSelection,
+    level: Entity,
+) {
+    let arena = versus.arena();
+    if *selection == arena {
+        level::restart(commands, next_state, level);
+    } else {
+        commands.insert_resource(arena);
+        next_state.set(AppState::Loading);
+    }
+}
+
+// a round goes to whoever is left once everyone else is all the way into a pit; if the last two
+// go in together, nobody gets it
+fn decide_round(
+    mut commands: Commands,
+    mut versus: ResMut<Versus>,
+    mut next_state: ResMut<NextState<AppState>>,
+    selection: Res<LevelSelection>,
+    level: Query<Entity, With<Handle<LdtkLevel>>>,
+    standing: Query<&PlayerInput, With<Orb>>,
+    falling: Query<(), (With<PlayerInput>, With<Falling>)>,
+) {
+    // several ticks can run before the next round is loaded, and the round is only won once
+    if next_state.0.is_some() || standing.iter().count() > 1 || !falling.is_empty() {
+        return;
+    }
+
+    match standing.get_single() {
+        Ok(PlayerInput(seat)) => {
+            versus.wins[seat.index()] += 1;
+            info!("Round {} to player {}", versus.round + 1, seat.number());
+        }
+        Err(_) => info!("Round {} drawn", versus.round + 1),
+    }
+
+    versus.round += 1;
+
+    if versus.winner().is_some() {
+        next_state.set(AppState::MatchOver);
+    } else if let Ok(level) = level.get_single() {
+        next_round(&mut commands, &mut next_state, &versus, &selection, level);
+    }
+}
+
+fn enter_match_over(mut commands: Commands, versus: Res<Versus>) {
+    let title = match versus.winner() {
+        Some(seat) => format!("Player {} wins!", seat.number()),
+        None => "Match over".into(),
+    };
+
+    let items = vec![
+        (versus.score_label(), MenuAction::Disabled),
+        ("Rematch".into(), MenuAction::Restart),
+        ("Quit to Menu".into(), MenuAction::QuitToMenu),
+    ];
+
+    // start on "Rematch", past the score
+    let menu = menu::spawn_menu(&mut commands, &title, items, 1);
+    commands.entity(menu).insert(MatchOverScreen);
+}
+
+fn handle_match_over_menu(
+    mut commands: Commands,
+    mut events: EventReader<MenuEvent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut versus: ResMut<Versus>,
+    selection: Res<LevelSelection>,
+    levels: Query<Entity, With<Handle<LdtkLevel>>>,
+    worlds: Query<Entity, With<Handle<LdtkAsset>>>,
+) {
+    for MenuEvent(action) in events.iter() {
+        match *action {
+            MenuAction::Restart => {
+                if let Ok(level) = levels.get_single() {
+                    *versus = Versus::new(std::mem::take(&mut versus.arenas));
+                    next_round(&mut commands, &mut next_state, &versus, &selection, level);
+                }
+            }
+            MenuAction::QuitToMenu => {
+                level::unload(&mut commands, &worlds);
+                next_state.set(AppState::Menu);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn end_match(mut commands: Commands) {
+    commands.remove_resource::<Versus>();
+}
+
+pub fn plugin() -> impl Plugin {
+    OpaquePlugin(|app| {
+        app.add_systems(
+            FixedUpdate,
+            decide_round
+                .in_set(TickSet::Outcome)
+                .run_if(resource_exists::<Versus>())
+                .run_if(in_state(AppState::Playing)),
+        )
+        .add_systems(
+            Update,
+            handle_match_over_menu.run_if(in_state(AppState::MatchOver)),
+        )
+        .add_systems(OnEnter(AppState::Menu), end_match)
+        .add_systems(OnEnter(AppState::MatchOver), enter_match_over)
+        .add_systems(
+            OnExit(AppState::MatchOver),
+            menu::despawn_menus::<MatchOverScreen>,
+        );
+    })
+}
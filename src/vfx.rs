@@ -10,6 +10,9 @@ const SPARK_SPEED: f32 = 15.0;
 const SPARK_COUNT: CpuValue<f32> = CpuValue::Uniform((4.0, 16.0));
 const SPARK_SIZE: CpuValue<Vec2> = CpuValue::Uniform((Vec2::new(2.0, 2.0), Vec2::new(8.0, 8.0)));
 
+// shoves are the same for everyone, so that they're easy to spot
+pub const SHOVE_COLOR: Vec4 = Vec4::new(1.0, 0.9, 0.4, 1.0);
+
 #[derive(Component)]
 struct Lifespan(Duration);
 
@@ -28,9 +31,16 @@ fn live_fast_die_young(
     }
 }
 
-pub fn plugin() -> impl Plugin {
-    OpaquePlugin(|app| {
-        app.add_plugins(HanabiPlugin).add_systems(
+pub fn plugin(headless: bool) -> impl Plugin {
+    OpaquePlugin(move |app| {
+        // effects are still allocated and attached without a renderer, they just never draw
+        if headless {
+            app.add_asset::<EffectAsset>();
+        } else {
+            app.add_plugins(HanabiPlugin);
+        }
+
+        app.add_systems(
             Update,
             live_fast_die_young.run_if(in_state(AppState::Playing)),
         );
@@ -76,7 +86,7 @@ pub fn allocate_thrust_sparks(
 
     effect.z_layer_2d = 4.0; // beneath entity layer
 
-    return effects.add(effect);
+    effects.add(effect)
 }
 
 pub fn instantiate_thrust_sparks(